@@ -5,25 +5,64 @@ use std::{
 
 use egui::{ProgressBar, Slider, SliderOrientation};
 
-use crate::timelapse::{self, TimelapseControl};
+use crate::{
+    screenshot::{GameDirs, Watcher},
+    timelapse::{
+        self, AspectRatio, CaptureRules, ConversionStatus, EventTriggers, FrameTransform,
+        NameTemplate, OutputFormat, ResizeFilter, Resolution, Schedule, Session, Settings,
+        SizeMismatch, SpaceGuard, StoreOptions, TimelapseControl,
+    },
+};
+
+/// Where the removed originals are kept, in the timelapse folder.
+const TRASH_FOLDER: &str = "Removed originals";
+
+/// The screenshot sizes assumed to estimate the space a timelapse needs.
+const SCREENSHOT_SIZE: Resolution = Resolution {
+    width: 1920,
+    height: 1080,
+};
+const HIGH_RES_SCREENSHOT_SIZE: Resolution = Resolution {
+    width: 3840,
+    height: 2160,
+};
 
 /// We derive Deserialize/Serialize so we can persist app state on shutdown.
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(default)] // if we add new fields, give them default values when deserializing old state
 pub struct TemplateApp {
+    /// Created when first needed, as the game directories may not be found
     #[serde(skip)]
-    screenshoter: crate::screenshot::Watcher,
+    screenshoter: Option<Watcher>,
+
+    /// Holds the screenshots taken outside of a timelapse, created when first needed
+    #[serde(skip)]
+    session: Option<Session>,
 
     #[serde(skip)]
     current_timelapse: Option<TimelapseControl>,
 
+    /// The stopped timelapses, still storing their last frames
+    #[serde(skip)]
+    finishing: Vec<TimelapseControl>,
+
     #[serde(skip)]
     stop_time: Option<Instant>,
 
+    /// When the current timelapse was paused, the stop time is pushed back on resume
+    #[serde(skip)]
+    paused_at: Option<Instant>,
+
     timelapse_folder: PathBuf,
 
     interval_seconds: u64,
 
+    capture_on_events: bool,
+
+    event_triggers: EventTriggers,
+
+    rules: CaptureRules,
+
     stop_after: bool,
 
     duration_minutes: u64,
@@ -33,6 +72,29 @@ pub struct TemplateApp {
     organize: bool,
 
     remove_original: bool,
+
+    /// Move the removed originals to a folder in the timelapse folder
+    trash_originals: bool,
+
+    format: OutputFormat,
+
+    transform: FrameTransform,
+
+    /// Write the game context in the frames
+    metadata: bool,
+
+    sidecar: bool,
+
+    /// What to do when the disks are almost full
+    space_guard: SpaceGuard,
+
+    /// The session folders in the timelapse folder
+    session_template: String,
+
+    /// Where the frames are stored in their session folder
+    name_template: String,
+
+    game_dirs: GameDirs,
 }
 
 impl Default for TemplateApp {
@@ -44,16 +106,31 @@ impl Default for TemplateApp {
             .to_owned()
             .join("Elite Dangerous Timelapses");
         Self {
-            screenshoter: crate::screenshot::Watcher::try_new().unwrap(),
+            screenshoter: None,
+            session: None,
             interval_seconds: 5,
+            capture_on_events: false,
+            event_triggers: EventTriggers::default(),
+            rules: CaptureRules::default(),
             stop_after: false,
             duration_minutes: 60,
             timelapse_folder,
             high_res: true,
             organize: true,
             remove_original: true,
+            trash_originals: false,
+            format: OutputFormat::default(),
+            transform: FrameTransform::default(),
+            metadata: true,
+            sidecar: false,
+            space_guard: SpaceGuard::default(),
+            session_template: timelapse::DEFAULT_SESSION_TEMPLATE.to_string(),
+            name_template: timelapse::DEFAULT_TEMPLATE.to_string(),
             current_timelapse: None,
+            finishing: vec![],
             stop_time: None,
+            paused_at: None,
+            game_dirs: GameDirs::default(),
         }
     }
 }
@@ -72,6 +149,62 @@ impl TemplateApp {
 
         Default::default()
     }
+
+    fn take_screenshot(&mut self) -> anyhow::Result<std::path::PathBuf> {
+        let store = self.store_options()?;
+        let screenshoter = match &mut self.screenshoter {
+            Some(screenshoter) => screenshoter,
+            None => self
+                .screenshoter
+                .insert(Watcher::with_dirs(&self.game_dirs)?),
+        };
+        let settings = Settings::new(None, self.rules.clone(), self.high_res, &store);
+        // A new session when the settings it was started with changed
+        let session = match self.session.take() {
+            Some(session)
+                if session.folder().parent() == Some(store.folder.as_path())
+                    && session.settings() == settings =>
+            {
+                session
+            }
+            previous => {
+                if let Some(previous) = previous {
+                    previous.stop();
+                }
+                Session::new(&store, settings)?
+            }
+        };
+        let session = self.session.insert(session);
+        timelapse::take_screenshot(screenshoter, session, "manual", self.high_res, &store)
+    }
+
+    fn store_options(&self) -> anyhow::Result<StoreOptions> {
+        Ok(StoreOptions {
+            folder: self.timelapse_folder.clone(),
+            session_template: NameTemplate::parse_session(&self.session_template)?,
+            template: NameTemplate::parse(&self.name_template)?,
+            format: self.format,
+            transform: self.transform.clone(),
+            metadata: self.metadata,
+            sidecar: self.sidecar,
+            organize: self.organize,
+            remove_original: self.remove_original,
+            trash: self
+                .trash_originals
+                .then(|| self.timelapse_folder.join(TRASH_FOLDER)),
+            space: self.space_guard.clone(),
+        })
+    }
+
+    /// The disk space the frames of the timelapse may take.
+    fn space_needed(&self, screenshots: u64) -> anyhow::Result<u64> {
+        let size = if self.high_res {
+            HIGH_RES_SCREENSHOT_SIZE
+        } else {
+            SCREENSHOT_SIZE
+        };
+        Ok(screenshots * timelapse::frame_size_estimate(&self.store_options()?, size))
+    }
 }
 
 impl eframe::App for TemplateApp {
@@ -80,6 +213,12 @@ impl eframe::App for TemplateApp {
         eframe::set_value(storage, eframe::APP_KEY, self);
     }
 
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        if let Some(session) = &self.session {
+            session.stop();
+        }
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         // repaint always, to account for external threads update
         ctx.request_repaint();
@@ -102,6 +241,20 @@ impl eframe::App for TemplateApp {
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.heading("Elite Dangerous Timelapse");
 
+            self.finishing.retain(|timelapse| !timelapse.is_finished());
+            for timelapse in &self.finishing {
+                let conversion = timelapse.conversion();
+                ui.horizontal(|ui| {
+                    let session = timelapse.session_folder().file_name().unwrap_or_default();
+                    ui.label(format!(
+                        "Finishing {} frames of {}",
+                        conversion.queued + conversion.converting,
+                        session.to_string_lossy()
+                    ));
+                    ui.spinner();
+                });
+            }
+
             if let Some(current_timelapse) = &mut self.current_timelapse {
                 current_timelapse.update_status();
                 match current_timelapse.status {
@@ -110,43 +263,102 @@ impl eframe::App for TemplateApp {
                         ui.spinner();
                     }
                     timelapse::Status::Waiting(next) => {
+                        let period = if self.capture_on_events {
+                            // The delay may be zero
+                            self.event_triggers.delay.max(Duration::from_secs(1))
+                        } else {
+                            Duration::from_secs(self.interval_seconds)
+                        };
                         ui.label(format!(
                             "Next in {}s",
                             1 + (next - Instant::now()).as_secs()
                         ));
                         ui.add(ProgressBar::new(
-                            (next - Instant::now()).as_secs_f32() / self.interval_seconds as f32,
+                            (next - Instant::now()).as_secs_f32() / period.as_secs_f32(),
                         ));
                     }
+                    timelapse::Status::WaitingForEvent => {
+                        ui.label("Waiting for a game event...");
+                    }
+                    timelapse::Status::Paused => {
+                        ui.label("Paused");
+                    }
+                    timelapse::Status::Suspended(reason) => {
+                        ui.label(format!("Paused automatically, {}", reason));
+                    }
+                    timelapse::Status::OutOfSpace(ref low) => {
+                        ui.colored_label(
+                            ui.visuals().warn_fg_color,
+                            format!("Paused automatically, {}", low),
+                        );
+                    }
+                    timelapse::Status::Stopped(ref low) => {
+                        ui.colored_label(
+                            ui.visuals().error_fg_color,
+                            format!("Stopped automatically, {}", low),
+                        );
+                    }
+                }
+                // Paused by the timelapse itself
+                if current_timelapse.is_paused() && self.paused_at.is_none() {
+                    self.paused_at = Some(Instant::now());
+                }
+                if self.organize {
+                    conversion_status(ui, current_timelapse.conversion());
+                }
+                if current_timelapse.is_stopped() {
+                    // Nothing to pause or resume
+                } else if current_timelapse.is_paused() {
+                    if ui.button("Resume Timelapse").clicked() {
+                        current_timelapse.resume();
+                        if let (Some(stop_time), Some(paused_at)) =
+                            (&mut self.stop_time, self.paused_at.take())
+                        {
+                            *stop_time += paused_at.elapsed();
+                        }
+                    }
+                } else if ui.button("Pause Timelapse").clicked() {
+                    current_timelapse.pause();
+                    self.paused_at = Some(Instant::now());
                 }
                 if ui.button("Stop Timelapse").clicked() {
-                    current_timelapse.stop();
-                    self.current_timelapse = None;
+                    current_timelapse.request_stop();
+                    self.finishing.extend(self.current_timelapse.take());
                 } else if let Some(stop_time) = self.stop_time {
-                    let remaining = stop_time - Instant::now();
+                    let now = self.paused_at.unwrap_or_else(Instant::now);
+                    let remaining = stop_time - now;
                     ui.label(format!("Stopping in {}m", 1 + (remaining.as_secs() / 60)));
-                    if Instant::now() > stop_time {
-                        current_timelapse.stop();
-                        self.current_timelapse = None;
+                    if now > stop_time {
+                        current_timelapse.request_stop();
+                        self.finishing.extend(self.current_timelapse.take());
                     }
                 }
             } else {
-                ui.add(
-                    Slider::new(&mut self.interval_seconds, 1..=3600)
-                        .logarithmic(true)
-                        .clamp_to_range(true)
-                        .smart_aim(true)
-                        .orientation(SliderOrientation::Horizontal)
-                        .trailing_fill(true)
-                        .custom_formatter(|x, _| {
-                            let x = x as u64;
-                            if x < 60 {
-                                format!("{}s", x)
-                            } else {
-                                format!("{}m{}s", x / 60, x % 60)
-                            }
-                        }),
-                );
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut self.capture_on_events, false, "Fixed interval");
+                    ui.radio_value(&mut self.capture_on_events, true, "Game events");
+                });
+                if self.capture_on_events {
+                    event_triggers(ui, &mut self.event_triggers);
+                } else {
+                    ui.add(
+                        Slider::new(&mut self.interval_seconds, 1..=3600)
+                            .logarithmic(true)
+                            .clamp_to_range(true)
+                            .smart_aim(true)
+                            .orientation(SliderOrientation::Horizontal)
+                            .trailing_fill(true)
+                            .custom_formatter(|x, _| {
+                                let x = x as u64;
+                                if x < 60 {
+                                    format!("{}s", x)
+                                } else {
+                                    format!("{}m{}s", x / 60, x % 60)
+                                }
+                            }),
+                    );
+                }
+                capture_rules(ui, &mut self.rules);
                 ui.checkbox(&mut self.stop_after, "Stop after");
                 if self.stop_after {
                     ui.add(
@@ -165,10 +377,22 @@ impl eframe::App for TemplateApp {
                                 }
                             }),
                     );
-                    ui.label(format!(
-                        "Number of screenshots: ~{}",
-                        60 * self.duration_minutes / self.interval_seconds
-                    ));
+                    if !self.capture_on_events {
+                        let screenshots = 60 * self.duration_minutes / self.interval_seconds;
+                        ui.horizontal(|ui| {
+                            ui.label(format!("Number of screenshots: ~{}", screenshots));
+                            if let Ok(space) = self.space_needed(screenshots) {
+                                ui.label(format!("Space needed: ~{}", format_size(space)))
+                                    .on_hover_text(format!(
+                                        "Assuming {}x{} screenshots, {}x{} in high resolution",
+                                        SCREENSHOT_SIZE.width,
+                                        SCREENSHOT_SIZE.height,
+                                        HIGH_RES_SCREENSHOT_SIZE.width,
+                                        HIGH_RES_SCREENSHOT_SIZE.height
+                                    ));
+                            }
+                        });
+                    }
                 }
                 ui.checkbox(&mut self.high_res, "High Resolution");
                 if self.high_res {
@@ -177,21 +401,70 @@ impl eframe::App for TemplateApp {
                 ui.checkbox(&mut self.organize, "Organize and convert the screenshots");
                 if self.organize {
                     ui.checkbox(&mut self.remove_original, "Remove Original");
+                    if self.remove_original {
+                        ui.checkbox(&mut self.trash_originals, "Keep them in a holding folder")
+                            .on_hover_text(format!(
+                                "Move the originals to \"{}\" in the timelapse folder",
+                                TRASH_FOLDER
+                            ));
+                    }
+                    output_format(ui, &mut self.format);
+                    ui.checkbox(&mut self.metadata, "Write the location in the files")
+                        .on_hover_text(
+                            "The system, body, station, commander and ship, as EXIF and XMP. \
+                             Not supported with AVIF",
+                        );
+                    ui.checkbox(&mut self.sidecar, "Write a JSON file next to each frame")
+                        .on_hover_text("The journal event and the game context of the frame");
+                    ui.collapsing("Crop and resize", |ui| {
+                        frame_transform(ui, &mut self.transform);
+                    });
+                    name_template(
+                        ui,
+                        "Session folders",
+                        "Placeholders: {date} {time} {year} {month} {day} {hour} {minute} \
+                         {second}, at the start of the session",
+                        &mut self.session_template,
+                        timelapse::DEFAULT_SESSION_TEMPLATE,
+                        NameTemplate::parse_session,
+                    );
+                    name_template(
+                        ui,
+                        "File names",
+                        "In the session folder. Placeholders: {date} {time} {year} {month} \
+                         {day} {hour} {minute} {second} {seq:05} {system} {body} {station} \
+                         {location} {commander} {ship} {trigger} {ext}",
+                        &mut self.name_template,
+                        timelapse::DEFAULT_TEMPLATE,
+                        NameTemplate::parse,
+                    );
                 }
+                ui.collapsing("Disk space", |ui| {
+                    space_guard(ui, &mut self.space_guard);
+                });
                 if ui.button("Start Timelapse").clicked() {
-                    self.current_timelapse = match TimelapseControl::start(
-                        self.timelapse_folder.clone(),
-                        Duration::from_secs(self.interval_seconds),
-                        self.high_res,
-                        self.organize,
-                        self.remove_original,
-                    ) {
+                    let schedule = if self.capture_on_events {
+                        Schedule::Events(self.event_triggers.clone())
+                    } else {
+                        Schedule::Interval(Duration::from_secs(self.interval_seconds))
+                    };
+                    self.current_timelapse = match self.store_options().and_then(|store| {
+                        let watcher = Watcher::with_dirs(&self.game_dirs)?;
+                        TimelapseControl::start(
+                            watcher,
+                            schedule,
+                            self.rules.clone(),
+                            self.high_res,
+                            store,
+                        )
+                    }) {
                         Ok(timelapse) => Some(timelapse),
                         Err(e) => {
                             log::error!("Failed to start timelapse: {}", e);
                             None
                         }
                     };
+                    self.paused_at = None;
                     self.stop_time = if self.stop_after {
                         Some(Instant::now() + Duration::from_secs(60 * self.duration_minutes))
                     } else {
@@ -200,16 +473,26 @@ impl eframe::App for TemplateApp {
                 }
 
                 if ui.button("Screenshot").clicked() {
-                    if let Err(e) = timelapse::take_screenshot(
-                        &mut self.screenshoter,
-                        self.high_res,
-                        self.organize,
-                        self.remove_original,
-                        &self.timelapse_folder,
-                    ) {
+                    if let Err(e) = self.take_screenshot() {
                         log::error!("Failed to take screenshot: {}", e);
                     }
                 }
+
+                ui.collapsing("Game directories", |ui| {
+                    let mut changed = directory_override(
+                        ui,
+                        "Journal directory",
+                        &mut self.game_dirs.journal_dir,
+                    );
+                    changed |= directory_override(
+                        ui,
+                        "Screenshot directory",
+                        &mut self.game_dirs.screenshot_dir,
+                    );
+                    if changed {
+                        self.screenshoter = None;
+                    }
+                });
             }
 
             if self.organize && ui.button("Open Timelapse Folder").clicked() {
@@ -237,6 +520,219 @@ impl eframe::App for TemplateApp {
     }
 }
 
+fn conversion_status(ui: &mut egui::Ui, conversion: ConversionStatus) {
+    let mut status = format!(
+        "Stored {} frames, {} waiting for conversion",
+        conversion.stored,
+        conversion.queued + conversion.converting
+    );
+    if let Some(mean_time) = conversion.mean_time {
+        status += &format!(", {:.1}s per frame", mean_time.as_secs_f32());
+    }
+    ui.label(status);
+    if conversion.failed > 0 {
+        ui.colored_label(
+            ui.visuals().warn_fg_color,
+            format!("{} frames failed, see the logs", conversion.failed),
+        );
+    }
+}
+
+/// A size in bytes, like `1.2 GB`.
+fn format_size(bytes: u64) -> String {
+    if bytes >= 1_000_000_000 {
+        format!("{:.1} GB", bytes as f64 / 1e9)
+    } else {
+        format!("{} MB", bytes / 1_000_000)
+    }
+}
+
+fn space_guard(ui: &mut egui::Ui, guard: &mut SpaceGuard) {
+    ui.label("When the disk of the screenshots or of the timelapse has less free space than");
+    for (threshold, label, default) in [
+        (&mut guard.warn_below, "Warn in the logs", 5_000),
+        (&mut guard.pause_below, "Pause the timelapse", 1_000),
+        (&mut guard.stop_below, "Stop the timelapse", 500),
+    ] {
+        ui.horizontal(|ui| {
+            let mut enabled = threshold.is_some();
+            ui.checkbox(&mut enabled, label);
+            if !enabled {
+                *threshold = None;
+            } else {
+                let megabytes = threshold.get_or_insert(default);
+                ui.add(
+                    egui::DragValue::new(megabytes)
+                        .clamp_range(0..=1_000_000)
+                        .speed(10)
+                        .suffix(" MB"),
+                );
+            }
+        });
+    }
+}
+
+fn event_triggers(ui: &mut egui::Ui, triggers: &mut EventTriggers) {
+    ui.checkbox(&mut triggers.fsd_jump, "Hyperspace jump");
+    ui.checkbox(&mut triggers.docking, "Docking and undocking");
+    ui.checkbox(&mut triggers.landing, "Touchdown and liftoff");
+    ui.checkbox(&mut triggers.supercruise_exit, "Supercruise exit");
+    ui.checkbox(&mut triggers.new_body_scan, "Scan of an undiscovered body");
+    let mut delay = triggers.delay.as_secs();
+    ui.horizontal(|ui| {
+        ui.label("Delay");
+        ui.add(
+            Slider::new(&mut delay, 0..=60)
+                .clamp_to_range(true)
+                .orientation(SliderOrientation::Horizontal)
+                .trailing_fill(true)
+                .suffix("s"),
+        );
+    });
+    triggers.delay = Duration::from_secs(delay);
+}
+
+fn capture_rules(ui: &mut egui::Ui, rules: &mut CaptureRules) {
+    ui.checkbox(
+        &mut rules.auto_pause,
+        "Pause in the main menu, the maps, the codex and when minimized",
+    );
+    ui.collapsing("Skipped frames", |ui| {
+        ui.checkbox(&mut rules.skip_hyperspace, "Hyperspace jumps");
+        ui.checkbox(&mut rules.skip_menus, "Main menu, maps and codex");
+        ui.checkbox(&mut rules.skip_station_services, "Station services");
+    });
+}
+
+fn output_format(ui: &mut egui::Ui, format: &mut OutputFormat) {
+    let (quality, webp_quality) = match *format {
+        OutputFormat::Jpeg { quality } | OutputFormat::Avif { quality } => (quality, None),
+        OutputFormat::WebP { quality } => (quality.unwrap_or(90), quality),
+        OutputFormat::Png => (90, None),
+    };
+    ui.horizontal(|ui| {
+        ui.label("Format");
+        ui.radio_value(format, OutputFormat::Png, "PNG");
+        ui.radio_value(format, OutputFormat::Jpeg { quality }, "JPEG");
+        ui.radio_value(
+            format,
+            OutputFormat::WebP {
+                quality: webp_quality,
+            },
+            "WebP",
+        );
+        ui.radio_value(format, OutputFormat::Avif { quality }, "AVIF")
+            .on_hover_text("Small files, but slow to encode");
+    });
+    if let OutputFormat::WebP { quality: webp } = format {
+        let mut lossless = webp.is_none();
+        if ui.checkbox(&mut lossless, "Lossless").changed() {
+            *webp = (!lossless).then_some(quality);
+        }
+    }
+    let mut lossless = 100;
+    let (quality, enabled) = match format {
+        OutputFormat::Jpeg { quality }
+        | OutputFormat::Avif { quality }
+        | OutputFormat::WebP {
+            quality: Some(quality),
+        } => (quality, true),
+        _ => (&mut lossless, false),
+    };
+    ui.horizontal(|ui| {
+        ui.add_enabled_ui(enabled, |ui| {
+            ui.label("Quality");
+            ui.add(
+                Slider::new(quality, 1..=100)
+                    .clamp_to_range(true)
+                    .orientation(SliderOrientation::Horizontal)
+                    .trailing_fill(true)
+                    .suffix("%"),
+            )
+        })
+        .response
+        .on_disabled_hover_text("Only for the lossy formats");
+    });
+}
+
+fn frame_transform(ui: &mut egui::Ui, transform: &mut FrameTransform) {
+    ui.horizontal(|ui| {
+        ui.label("Aspect ratio");
+        ui.radio_value(&mut transform.aspect, None, "Unchanged");
+        for aspect in AspectRatio::ALL {
+            ui.radio_value(&mut transform.aspect, Some(aspect), aspect.to_string());
+        }
+    });
+    let mut downscale = transform.max_size.is_some();
+    ui.checkbox(&mut downscale, "Downscale");
+    if !downscale {
+        transform.max_size = None;
+    } else {
+        let max_size = transform.max_size.get_or_insert_with(Resolution::default);
+        ui.horizontal(|ui| {
+            ui.label("To fit in");
+            ui.add(egui::DragValue::new(&mut max_size.width).clamp_range(16..=16384));
+            ui.label("x");
+            ui.add(egui::DragValue::new(&mut max_size.height).clamp_range(16..=16384));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Filter");
+            ui.radio_value(&mut transform.filter, ResizeFilter::Nearest, "Nearest");
+            ui.radio_value(&mut transform.filter, ResizeFilter::Triangle, "Bilinear");
+            ui.radio_value(&mut transform.filter, ResizeFilter::CatmullRom, "Bicubic");
+            ui.radio_value(&mut transform.filter, ResizeFilter::Lanczos3, "Lanczos");
+        });
+    }
+    ui.horizontal(|ui| {
+        ui.label("Frames of another size than the first")
+            .on_hover_text("For example after resizing the game window");
+        ui.radio_value(&mut transform.mismatch, SizeMismatch::Keep, "Keep");
+        ui.radio_value(&mut transform.mismatch, SizeMismatch::Pad, "Pad");
+        ui.radio_value(&mut transform.mismatch, SizeMismatch::Reject, "Reject");
+    });
+}
+
+fn name_template(
+    ui: &mut egui::Ui,
+    label: &str,
+    placeholders: &str,
+    template: &mut String,
+    default: &str,
+    parse: fn(&str) -> anyhow::Result<NameTemplate>,
+) {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        ui.text_edit_singleline(template)
+            .on_hover_text(placeholders);
+        if ui.button("Reset").clicked() {
+            *template = default.to_string();
+        }
+    });
+    if let Err(e) = parse(template) {
+        ui.colored_label(ui.visuals().error_fg_color, e.to_string());
+    }
+}
+
+/// Edit an optional directory, left empty for the default one. Returns true
+/// if it changed.
+fn directory_override(ui: &mut egui::Ui, label: &str, dir: &mut Option<PathBuf>) -> bool {
+    let mut text = dir
+        .as_ref()
+        .map(|dir| dir.display().to_string())
+        .unwrap_or_default();
+    let changed = ui
+        .horizontal(|ui| {
+            ui.label(label);
+            let edit = egui::TextEdit::singleline(&mut text).hint_text("Detected");
+            ui.add(edit).changed()
+        })
+        .inner;
+    if changed {
+        *dir = (!text.trim().is_empty()).then(|| text.into());
+    }
+    changed
+}
+
 fn powered_by_egui_and_eframe(ui: &mut egui::Ui) {
     ui.horizontal(|ui| {
         ui.spacing_mut().item_spacing.x = 0.0;
@@ -2,5 +2,7 @@
 
 mod app;
 pub mod screenshot;
+#[cfg(any(test, feature = "sim"))]
+pub mod sim;
 pub mod timelapse;
 pub use app::TemplateApp;
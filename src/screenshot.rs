@@ -1,83 +1,216 @@
 use std::{
-    path::PathBuf,
-    sync::mpsc::{Receiver, Sender},
-    time::Duration,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{mpsc::Receiver, Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
 };
 
 use anyhow::{bail, Context, Result};
+use chrono::{DateTime, SubsecRound, Utc};
 use ed_journals::logs::content::log_event_content::screenshot_event::ScreenshotEvent;
 
-use self::watch::Exit;
+use self::watch::{JournalScreenshot, Shared};
 
-mod request;
+pub use self::complete::{wait_until_complete, IncompleteScreenshot};
+pub use self::context::{CaptureContext, FlightState};
+pub use self::dirs::{default_journal_dir, default_screenshot_dir, GameDirs};
+pub use self::status::{GameStatus, GuiFocus};
+pub use self::trigger::ScreenshotTrigger;
+pub use self::watch::{GameEvent, GameState};
+
+pub mod bindings;
+mod complete;
+mod context;
+mod dirs;
+mod status;
+pub mod trigger;
 mod watch;
 
 #[derive(Debug)]
 pub struct Screenshot {
     pub path: PathBuf,
     pub location: String,
+    /// When the screenshot was logged in the journal.
+    pub timestamp: DateTime<Utc>,
+    /// The game state when the screenshot was taken.
+    pub context: CaptureContext,
+    /// The journal event, as the game logged it.
+    pub event: ScreenshotEvent,
 }
 
 pub struct Watcher {
-    rx: Receiver<ScreenshotEvent>,
-    exit_tx: Sender<watch::Exit>,
+    rx: Receiver<JournalScreenshot>,
+    _watch: watch::Handle,
+    shared: Arc<Mutex<Shared>>,
+    trigger: Box<dyn ScreenshotTrigger>,
+    screenshot_dir: PathBuf,
 }
 
 impl Watcher {
+    /// Create a watcher using the screenshot trigger of the current platform
+    /// and the game's default directories.
     pub fn try_new() -> Result<Self> {
-        let (rx, exit_tx) = watch::watch_screenshots()?;
-        Ok(Self { rx, exit_tx })
+        Self::with_dirs(&GameDirs::default())
+    }
+
+    /// Create a watcher using the screenshot trigger of the current platform.
+    pub fn with_dirs(dirs: &GameDirs) -> Result<Self> {
+        let journal_dir = dirs.journal_dir()?;
+        Self::new(
+            trigger::platform_default(&journal_dir)?,
+            journal_dir,
+            dirs.screenshot_dir()?,
+        )
+    }
+
+    pub fn new(
+        trigger: Box<dyn ScreenshotTrigger>,
+        journal_dir: PathBuf,
+        screenshot_dir: PathBuf,
+    ) -> Result<Self> {
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let (rx, watch) = watch::watch_screenshots(journal_dir, shared.clone())?;
+        Ok(Self {
+            rx,
+            _watch: watch,
+            shared,
+            trigger,
+            screenshot_dir,
+        })
+    }
+
+    /// Call `listener` with every game event, until it returns false.
+    ///
+    /// It is first called with [`GameEvent::Subscribed`] and the current state.
+    /// A new watcher reads the current journal from its start, so the events
+    /// logged before may be seen too.
+    pub fn subscribe(&self, listener: impl FnMut(&GameEvent, &GameState) -> bool + Send + 'static) {
+        self.shared.lock().unwrap().subscribe(Box::new(listener));
+    }
+
+    /// The game state, as far as the watcher has read.
+    pub fn game_state(&self) -> GameState {
+        self.shared.lock().unwrap().state.clone()
+    }
+
+    /// Where the game writes the screenshots.
+    pub fn screenshot_dir(&self) -> &Path {
+        &self.screenshot_dir
+    }
+
+    /// Whether the game window is minimized, false when it can't be told.
+    pub fn game_minimized(&mut self) -> bool {
+        self.trigger.game_minimized().unwrap_or_else(|e| {
+            log::debug!("Failed to check the game window: {}", e);
+            false
+        })
     }
 
     pub fn take_screenshot(&mut self, high_res: bool) -> Result<Screenshot> {
         // Empty the screenshot channel
-        while self.rx.recv_timeout(Duration::from_millis(100)).is_ok() {}
+        while let Ok(stale) = self.rx.recv_timeout(Duration::from_millis(100)) {
+            log::warn!(
+                "Ignoring a screenshot taken before the request: {}",
+                stale.event.filename
+            );
+        }
 
-        // Request a screenshot
-        request::request_screenshot(high_res)?;
+        let existing = self.existing_files()?;
+        // The journal timestamps are rounded to the second
+        let requested = Utc::now().trunc_subsecs(0);
 
-        // Wait for the screenshot
-        let screenshot = self.rx.recv_timeout(Duration::from_secs(10))?;
+        // Request a screenshot
+        self.trigger.trigger(high_res)?;
 
-        screenshot.try_into()
+        // Wait for the screenshot, ignoring the ones that are not ours
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let screenshot = self
+                .rx
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                .context("The screenshot did not appear in the journal")?;
+            let path = event_path(&screenshot.event, &self.screenshot_dir)?;
+            if screenshot.timestamp < requested {
+                log::warn!(
+                    "Ignoring a screenshot taken at {}, before the request: {}",
+                    screenshot.timestamp,
+                    path.display()
+                );
+            } else if existing
+                .get(&path)
+                .is_some_and(|&modified| modified_time(&path) == Some(modified))
+            {
+                log::warn!(
+                    "Ignoring a screenshot of an already existing file: {}",
+                    path.display()
+                );
+            } else {
+                return Screenshot::from_event(
+                    screenshot.timestamp,
+                    screenshot.event,
+                    screenshot.context,
+                    &self.screenshot_dir,
+                );
+            }
+        }
     }
-}
 
-impl Drop for Watcher {
-    fn drop(&mut self) {
-        if let Err(e) = self.exit_tx.send(Exit) {
-            log::error!("Failed to send exit signal to screenshot watcher: {}", e);
+    /// The files of the screenshot directory, with their modification time.
+    ///
+    /// The originals may be removed meanwhile, and their names reused by the
+    /// game, so a file is only the same while it isn't modified.
+    fn existing_files(&self) -> Result<HashMap<PathBuf, SystemTime>> {
+        if !self.screenshot_dir.is_dir() {
+            return Ok(HashMap::new());
         }
+        Ok(std::fs::read_dir(&self.screenshot_dir)
+            .context("Failed to list the screenshot directory")?
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let modified = modified_time(&path)?;
+                Some((path, modified))
+            })
+            .collect())
     }
 }
 
-impl TryFrom<ScreenshotEvent> for Screenshot {
-    type Error = anyhow::Error;
-
-    fn try_from(value: ScreenshotEvent) -> Result<Self, Self::Error> {
-        let picture_dir = directories::UserDirs::new()
-            .context("Failed to find home directory")?
-            .picture_dir()
-            .context("Failed to find picture directory")?
-            .to_owned();
-        let screenshot_dir = picture_dir
-            .join("Frontier Developments")
-            .join("Elite Dangerous");
-
-        // weird ED_Pictures prefix in the file name
-        let filename = value
-            .filename
-            .split('\\')
-            .last()
-            .context("Failed to split")?;
-        let path = screenshot_dir.join(filename);
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Where the screenshot of an event is stored.
+fn event_path(event: &ScreenshotEvent, screenshot_dir: &Path) -> Result<PathBuf> {
+    // weird ED_Pictures prefix in the file name
+    let filename = event
+        .filename
+        .split('\\')
+        .next_back()
+        .context("Failed to split")?;
+    Ok(screenshot_dir.join(filename))
+}
+
+impl Screenshot {
+    pub fn from_event(
+        timestamp: DateTime<Utc>,
+        value: ScreenshotEvent,
+        context: CaptureContext,
+        screenshot_dir: &Path,
+    ) -> Result<Self> {
+        let path = event_path(&value, screenshot_dir)?;
         if !path.is_file() {
             bail!("Screenshot file does not exist");
         }
         let location = value
             .body
-            .or(value.system)
+            .clone()
+            .or_else(|| value.system.clone())
             .unwrap_or_else(|| "Unknown location".to_string());
-        Ok(Self { path, location })
+        Ok(Self {
+            path,
+            location,
+            timestamp,
+            context,
+            event: value,
+        })
     }
 }
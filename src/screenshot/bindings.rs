@@ -0,0 +1,531 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+const SCREENSHOT: &str = "Screenshot";
+const HIGH_RES_SCREENSHOT: &str = "HighResScreenShot";
+
+/// A keyboard key, identified by its set 1 scancode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    pub scancode: u16,
+    /// Whether the key is sent with the `E0` prefix.
+    pub extended: bool,
+}
+
+impl Key {
+    pub const F10: Key = Key::new(0x44);
+    pub const LEFT_ALT: Key = Key::new(0x38);
+
+    const fn new(scancode: u16) -> Self {
+        Self {
+            scancode,
+            extended: false,
+        }
+    }
+
+    const fn extended(scancode: u16) -> Self {
+        Self {
+            scancode,
+            extended: true,
+        }
+    }
+
+    /// Convert a key name used in the `.binds` files.
+    pub fn from_binds_name(name: &str) -> Option<Self> {
+        let name = name.strip_prefix("Key_")?;
+        let key = match name {
+            "Escape" => Key::new(0x01),
+            "1" => Key::new(0x02),
+            "2" => Key::new(0x03),
+            "3" => Key::new(0x04),
+            "4" => Key::new(0x05),
+            "5" => Key::new(0x06),
+            "6" => Key::new(0x07),
+            "7" => Key::new(0x08),
+            "8" => Key::new(0x09),
+            "9" => Key::new(0x0A),
+            "0" => Key::new(0x0B),
+            "Minus" => Key::new(0x0C),
+            "Equals" => Key::new(0x0D),
+            "Backspace" => Key::new(0x0E),
+            "Tab" => Key::new(0x0F),
+            "Q" => Key::new(0x10),
+            "W" => Key::new(0x11),
+            "E" => Key::new(0x12),
+            "R" => Key::new(0x13),
+            "T" => Key::new(0x14),
+            "Y" => Key::new(0x15),
+            "U" => Key::new(0x16),
+            "I" => Key::new(0x17),
+            "O" => Key::new(0x18),
+            "P" => Key::new(0x19),
+            "LeftBracket" => Key::new(0x1A),
+            "RightBracket" => Key::new(0x1B),
+            "Enter" => Key::new(0x1C),
+            "LeftControl" => Key::new(0x1D),
+            "A" => Key::new(0x1E),
+            "S" => Key::new(0x1F),
+            "D" => Key::new(0x20),
+            "F" => Key::new(0x21),
+            "G" => Key::new(0x22),
+            "H" => Key::new(0x23),
+            "J" => Key::new(0x24),
+            "K" => Key::new(0x25),
+            "L" => Key::new(0x26),
+            "SemiColon" => Key::new(0x27),
+            "Apostrophe" => Key::new(0x28),
+            "Grave" => Key::new(0x29),
+            "LeftShift" => Key::new(0x2A),
+            "BackSlash" => Key::new(0x2B),
+            "Z" => Key::new(0x2C),
+            "X" => Key::new(0x2D),
+            "C" => Key::new(0x2E),
+            "V" => Key::new(0x2F),
+            "B" => Key::new(0x30),
+            "N" => Key::new(0x31),
+            "M" => Key::new(0x32),
+            "Comma" => Key::new(0x33),
+            "Period" => Key::new(0x34),
+            "Slash" => Key::new(0x35),
+            "RightShift" => Key::new(0x36),
+            "Numpad_Multiply" => Key::new(0x37),
+            "LeftAlt" => Key::LEFT_ALT,
+            "Space" => Key::new(0x39),
+            "CapsLock" => Key::new(0x3A),
+            "F1" => Key::new(0x3B),
+            "F2" => Key::new(0x3C),
+            "F3" => Key::new(0x3D),
+            "F4" => Key::new(0x3E),
+            "F5" => Key::new(0x3F),
+            "F6" => Key::new(0x40),
+            "F7" => Key::new(0x41),
+            "F8" => Key::new(0x42),
+            "F9" => Key::new(0x43),
+            "F10" => Key::F10,
+            "NumLock" => Key::new(0x45),
+            "ScrollLock" => Key::new(0x46),
+            "Numpad_7" => Key::new(0x47),
+            "Numpad_8" => Key::new(0x48),
+            "Numpad_9" => Key::new(0x49),
+            "Numpad_Subtract" => Key::new(0x4A),
+            "Numpad_4" => Key::new(0x4B),
+            "Numpad_5" => Key::new(0x4C),
+            "Numpad_6" => Key::new(0x4D),
+            "Numpad_Add" => Key::new(0x4E),
+            "Numpad_1" => Key::new(0x4F),
+            "Numpad_2" => Key::new(0x50),
+            "Numpad_3" => Key::new(0x51),
+            "Numpad_0" => Key::new(0x52),
+            "Numpad_Decimal" => Key::new(0x53),
+            "F11" => Key::new(0x57),
+            "F12" => Key::new(0x58),
+            "NumpadEnter" | "Numpad_Enter" => Key::extended(0x1C),
+            "RightControl" => Key::extended(0x1D),
+            "Numpad_Divide" => Key::extended(0x35),
+            "RightAlt" => Key::extended(0x38),
+            "Home" => Key::extended(0x47),
+            "UpArrow" => Key::extended(0x48),
+            "PageUp" => Key::extended(0x49),
+            "LeftArrow" => Key::extended(0x4B),
+            "RightArrow" => Key::extended(0x4D),
+            "End" => Key::extended(0x4F),
+            "DownArrow" => Key::extended(0x50),
+            "PageDown" => Key::extended(0x51),
+            "Insert" => Key::extended(0x52),
+            "Delete" => Key::extended(0x53),
+            _ => return None,
+        };
+        Some(key)
+    }
+
+    /// Linux input event code of the key.
+    ///
+    /// It is the same as the scancode, except for the extended keys.
+    pub fn evdev(&self) -> u16 {
+        if !self.extended {
+            return self.scancode;
+        }
+        match self.scancode {
+            0x1C => 96,
+            0x1D => 97,
+            0x35 => 98,
+            0x38 => 100,
+            0x47 => 102,
+            0x48 => 103,
+            0x49 => 104,
+            0x4B => 105,
+            0x4D => 106,
+            0x4F => 107,
+            0x50 => 108,
+            0x51 => 109,
+            0x52 => 110,
+            0x53 => 111,
+            other => other,
+        }
+    }
+}
+
+/// A key with its modifiers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyCombo {
+    pub modifiers: Vec<Key>,
+    pub key: Key,
+}
+
+impl KeyCombo {
+    /// The keys in the order they should be pressed.
+    pub fn keys(&self) -> Vec<Key> {
+        let mut keys = self.modifiers.clone();
+        keys.push(self.key);
+        keys
+    }
+}
+
+/// The keys bound to the screenshot actions in game.
+///
+/// An action without a keyboard binding is only an error when it is used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScreenshotKeys {
+    pub screenshot: Option<KeyCombo>,
+    pub high_res: Option<KeyCombo>,
+}
+
+impl Default for ScreenshotKeys {
+    fn default() -> Self {
+        Self {
+            screenshot: Some(KeyCombo {
+                modifiers: vec![],
+                key: Key::F10,
+            }),
+            high_res: Some(KeyCombo {
+                modifiers: vec![Key::LEFT_ALT],
+                key: Key::F10,
+            }),
+        }
+    }
+}
+
+impl ScreenshotKeys {
+    /// Read the keys from the active bindings of the player whose journals
+    /// are in `journal_dir`.
+    ///
+    /// Falls back to the default keys when the game uses one of its built-in
+    /// presets, or when the bindings can't be found.
+    pub fn load(journal_dir: &Path) -> Result<Self> {
+        let Some(bindings_dir) = bindings_dir(journal_dir) else {
+            log::warn!("Failed to find the bindings directory, using the default screenshot keys");
+            return Ok(Self::default());
+        };
+        match active_binds_file(&bindings_dir)? {
+            Some(binds_file) => {
+                log::info!("Reading the screenshot keys from {}", binds_file.display());
+                Self::from_binds_file(&binds_file)
+            }
+            None => {
+                log::info!("No custom bindings, using the default screenshot keys");
+                Ok(Self::default())
+            }
+        }
+    }
+
+    pub fn from_binds_file(path: &Path) -> Result<Self> {
+        let xml = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::parse(&xml).with_context(|| format!("Invalid bindings in {}", path.display()))
+    }
+
+    /// Parse the content of a `.binds` file.
+    pub fn parse(xml: &str) -> Result<Self> {
+        let document = roxmltree::Document::parse(xml)?;
+        let root = document.root_element();
+        Ok(Self {
+            screenshot: keyboard_binding(root, SCREENSHOT),
+            high_res: keyboard_binding(root, HIGH_RES_SCREENSHOT),
+        })
+    }
+
+    /// The keys of the screenshot action, failing when none is bound.
+    pub fn combo(&self, high_res: bool) -> Result<&KeyCombo> {
+        let (combo, action) = if high_res {
+            (&self.high_res, HIGH_RES_SCREENSHOT)
+        } else {
+            (&self.screenshot, SCREENSHOT)
+        };
+        combo.as_ref().with_context(|| {
+            format!(
+                "No keyboard key is bound to the {} action, bind one in the game options",
+                action
+            )
+        })
+    }
+}
+
+/// Find the first usable keyboard binding of an action, primary or
+/// secondary.
+fn keyboard_binding(root: roxmltree::Node<'_, '_>, action: &str) -> Option<KeyCombo> {
+    let Some(action_node) = root.children().find(|n| n.has_tag_name(action)) else {
+        log::warn!("The {} action is missing from the bindings", action);
+        return None;
+    };
+    action_node
+        .children()
+        .filter(|n| n.has_tag_name("Primary") || n.has_tag_name("Secondary"))
+        .filter(|binding| is_keyboard(*binding))
+        .find_map(|binding| match key_combo(binding) {
+            Ok(combo) => Some(combo),
+            Err(e) => {
+                log::warn!(
+                    "Skipping the {} binding of the {} action: {}",
+                    binding.tag_name().name(),
+                    action,
+                    e
+                );
+                None
+            }
+        })
+}
+
+fn key_combo(binding: roxmltree::Node<'_, '_>) -> Result<KeyCombo> {
+    let key = parse_key(binding)?;
+    let modifiers = binding
+        .children()
+        .filter(|n| n.has_tag_name("Modifier"))
+        .map(|modifier| {
+            if !is_keyboard(modifier) {
+                bail!("Non-keyboard modifier");
+            }
+            parse_key(modifier)
+        })
+        .collect::<Result<_>>()?;
+    Ok(KeyCombo { modifiers, key })
+}
+
+fn is_keyboard(node: roxmltree::Node<'_, '_>) -> bool {
+    node.attribute("Device") == Some("Keyboard")
+}
+
+fn parse_key(node: roxmltree::Node<'_, '_>) -> Result<Key> {
+    let name = node.attribute("Key").unwrap_or_default();
+    Key::from_binds_name(name).with_context(|| format!("Unsupported key {}", name))
+}
+
+/// The directory where the game stores the bindings of the player whose
+/// journals are in `journal_dir`.
+pub fn bindings_dir(journal_dir: &Path) -> Option<PathBuf> {
+    // AppData is next to the "Saved Games" folder holding the journals, in a
+    // Wine prefix too
+    let local_app_data = match super::dirs::windows_user_dir(journal_dir) {
+        Some(user_dir) => user_dir.join("AppData").join("Local"),
+        #[cfg(windows)]
+        None => directories::BaseDirs::new()?.data_local_dir().to_owned(),
+        #[cfg(not(windows))]
+        None => return None,
+    };
+
+    let dir = local_app_data
+        .join("Frontier Developments")
+        .join("Elite Dangerous")
+        .join("Options")
+        .join("Bindings");
+    dir.is_dir().then_some(dir)
+}
+
+/// Find the `.binds` file of the preset selected in game, if it is a custom one.
+fn active_binds_file(bindings_dir: &Path) -> Result<Option<PathBuf>> {
+    let Some(start_preset) = newest_file(bindings_dir, |name| {
+        name.starts_with("StartPreset") && name.ends_with(".start")
+    })?
+    else {
+        return Ok(None);
+    };
+    let content = std::fs::read_to_string(&start_preset)
+        .with_context(|| format!("Failed to read {}", start_preset.display()))?;
+    // One line per context (general, ship, SRV, on foot), the screenshot
+    // actions are in the general one.
+    let Some(preset) = content.lines().map(str::trim).find(|l| !l.is_empty()) else {
+        return Ok(None);
+    };
+    let prefix = format!("{}.", preset);
+    newest_file(bindings_dir, |name| {
+        name.starts_with(&prefix) && name.ends_with(".binds")
+    })
+}
+
+fn newest_file(dir: &Path, filter: impl Fn(&str) -> bool) -> Result<Option<PathBuf>> {
+    let mut newest = None;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_name().to_str().is_some_and(&filter) {
+            continue;
+        }
+        let modified = entry.metadata()?.modified()?;
+        if newest.as_ref().map_or(true, |(t, _)| modified > *t) {
+            newest = Some((modified, entry.path()));
+        }
+    }
+    Ok(newest.map(|(_, path)| path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `.binds` file with these screenshot actions.
+    fn binds(actions: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8" ?>
+<Root PresetName="Custom" MajorVersion="4" MinorVersion="0">
+{}
+</Root>"#,
+            actions
+        )
+    }
+
+    fn combo(modifiers: &[Key], key: Key) -> Option<KeyCombo> {
+        Some(KeyCombo {
+            modifiers: modifiers.to_vec(),
+            key,
+        })
+    }
+
+    #[test]
+    fn primary_key_is_used() {
+        let keys = ScreenshotKeys::parse(&binds(
+            r#"<Screenshot>
+  <Primary Device="Keyboard" Key="Key_F9" />
+  <Secondary Device="Keyboard" Key="Key_F10" />
+</Screenshot>"#,
+        ))
+        .unwrap();
+
+        assert_eq!(keys.screenshot, combo(&[], Key::new(0x43)));
+    }
+
+    #[test]
+    fn secondary_key_is_the_fallback() {
+        let keys = ScreenshotKeys::parse(&binds(
+            r#"<Screenshot>
+  <Primary Device="ThrustMasterHOTAS4" Key="Joy_1" />
+  <Secondary Device="Keyboard" Key="Key_F10" />
+</Screenshot>
+<HighResScreenShot>
+  <Primary Device="Keyboard" Key="Key_Unknown" />
+  <Secondary Device="Keyboard" Key="Key_End" />
+</HighResScreenShot>"#,
+        ))
+        .unwrap();
+
+        assert_eq!(keys.screenshot, combo(&[], Key::F10));
+        assert_eq!(keys.high_res, combo(&[], Key::extended(0x4F)));
+    }
+
+    #[test]
+    fn modifiers_are_pressed_first() {
+        let keys = ScreenshotKeys::parse(&binds(
+            r#"<HighResScreenShot>
+  <Primary Device="Keyboard" Key="Key_F10">
+    <Modifier Device="Keyboard" Key="Key_LeftAlt" />
+  </Primary>
+  <Secondary Device="{NoDevice}" Key="" />
+</HighResScreenShot>"#,
+        ))
+        .unwrap();
+
+        assert_eq!(keys.high_res, combo(&[Key::LEFT_ALT], Key::F10));
+        assert_eq!(
+            keys.combo(true).unwrap().keys(),
+            vec![Key::LEFT_ALT, Key::F10]
+        );
+    }
+
+    #[test]
+    fn non_keyboard_bindings_are_ignored() {
+        let keys = ScreenshotKeys::parse(&binds(
+            r#"<Screenshot>
+  <Primary Device="ThrustMasterHOTAS4" Key="Joy_1" />
+  <Secondary Device="Keyboard" Key="Key_F10">
+    <Modifier Device="ThrustMasterHOTAS4" Key="Joy_2" />
+  </Secondary>
+</Screenshot>"#,
+        ))
+        .unwrap();
+
+        assert_eq!(keys.screenshot, None);
+    }
+
+    #[test]
+    fn missing_action_fails_only_when_used() {
+        let keys = ScreenshotKeys::parse(&binds(
+            r#"<Screenshot>
+  <Primary Device="Keyboard" Key="Key_F10" />
+</Screenshot>"#,
+        ))
+        .unwrap();
+
+        assert!(keys.combo(false).is_ok());
+        let error = keys.combo(true).unwrap_err().to_string();
+        assert!(error.contains("HighResScreenShot"), "{}", error);
+        assert!(error.contains("bind one in the game options"), "{}", error);
+    }
+
+    #[test]
+    fn unsupported_keys_are_skipped() {
+        let keys = ScreenshotKeys::parse(&binds(
+            r#"<Screenshot>
+  <Primary Device="Keyboard" Key="Key_Unknown" />
+  <Secondary Device="Keyboard" Key="Key_F10">
+    <Modifier Device="Keyboard" Key="Key_Unknown" />
+  </Secondary>
+</Screenshot>"#,
+        ))
+        .unwrap();
+
+        assert_eq!(keys.screenshot, None);
+        assert!(keys.combo(false).is_err());
+    }
+
+    #[test]
+    fn active_binds_file_follows_the_start_preset() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(active_binds_file(dir.path()).unwrap(), None);
+
+        std::fs::write(dir.path().join("Other.4.0.binds"), binds("")).unwrap();
+        std::fs::write(dir.path().join("Custom.4.0.binds"), binds("")).unwrap();
+        std::fs::write(
+            dir.path().join("StartPreset.4.start"),
+            "Custom\nOther\nOther\nOther\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            active_binds_file(dir.path()).unwrap(),
+            Some(dir.path().join("Custom.4.0.binds"))
+        );
+    }
+
+    #[test]
+    fn bindings_are_found_next_to_the_journals() {
+        let root = tempfile::tempdir().unwrap();
+        let user_dir = root.path().join("prefix/drive_c/users/cmdr");
+        let journal_dir = user_dir.join("Saved Games/Frontier Developments/Elite Dangerous");
+        let bindings =
+            user_dir.join("AppData/Local/Frontier Developments/Elite Dangerous/Options/Bindings");
+        std::fs::create_dir_all(&journal_dir).unwrap();
+        std::fs::create_dir_all(&bindings).unwrap();
+
+        assert_eq!(bindings_dir(&journal_dir), Some(bindings));
+    }
+
+    #[test]
+    fn built_in_preset_has_no_binds_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("StartPreset.4.start"),
+            "KeyboardMouseOnly\n",
+        )
+        .unwrap();
+
+        assert_eq!(active_binds_file(dir.path()).unwrap(), None);
+    }
+}
@@ -0,0 +1,187 @@
+use std::{
+    fmt::Display,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How long the size of a file must stay the same for it to be complete,
+/// when its header doesn't tell its size.
+const SETTLE_TIME: Duration = Duration::from_millis(250);
+/// The BMP file header and the smallest info header.
+const BMP_HEADER_LEN: u64 = 26;
+
+/// The game didn't finish writing a screenshot in time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncompleteScreenshot {
+    pub path: PathBuf,
+    pub size: u64,
+    /// The size written in the header of the file, if it could be read.
+    pub expected: Option<u64>,
+}
+
+impl Display for IncompleteScreenshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The screenshot {} is still incomplete, {} bytes",
+            self.path.display(),
+            self.size
+        )?;
+        if let Some(expected) = self.expected {
+            write!(f, " of {}", expected)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for IncompleteScreenshot {}
+
+/// Wait for the game to finish writing a screenshot.
+///
+/// A BMP is complete when it is as long as its header tells, other files,
+/// or BMPs whose header tells no valid size, when their size stops
+/// changing. Fails with [`IncompleteScreenshot`] after
+/// the timeout.
+pub fn wait_until_complete(path: &Path, timeout: Duration) -> Result<()> {
+    let deadline = Instant::now() + timeout;
+    let is_bmp = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("bmp"));
+    let mut last_size = None;
+    let mut stable_since = Instant::now();
+    loop {
+        let size = std::fs::metadata(path)?.len();
+        let expected = if is_bmp { bmp_size(path)? } else { None };
+        if last_size != Some(size) {
+            last_size = Some(size);
+            stable_since = Instant::now();
+        }
+        let complete = match expected {
+            Some(expected) => size >= expected,
+            None if is_bmp && size < BMP_HEADER_LEN => false,
+            None => size > 0 && stable_since.elapsed() >= SETTLE_TIME,
+        };
+        if complete {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(IncompleteScreenshot {
+                path: path.to_owned(),
+                size,
+                expected,
+            }
+            .into());
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// The file size from the BMP header, if it is already written and valid.
+fn bmp_size(path: &Path) -> Result<Option<u64>> {
+    let mut header = [0; 6];
+    let mut file = File::open(path)?;
+    let mut read = 0;
+    while read < header.len() {
+        match file.read(&mut header[read..])? {
+            0 => return Ok(None),
+            n => read += n,
+        }
+    }
+    if &header[..2] != b"BM" {
+        return Ok(None);
+    }
+    let size = u64::from(u32::from_le_bytes([
+        header[2], header[3], header[4], header[5],
+    ]));
+    // Some writers leave it at zero
+    Ok((size >= BMP_HEADER_LEN).then_some(size))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::OpenOptions, io::Write};
+
+    use super::*;
+
+    /// A BMP screenshot, encoded in memory.
+    fn bmp() -> Vec<u8> {
+        let mut bmp = std::io::Cursor::new(vec![]);
+        image::RgbImage::new(64, 36)
+            .write_to(&mut bmp, image::ImageFormat::Bmp)
+            .unwrap();
+        bmp.into_inner()
+    }
+
+    #[test]
+    fn screenshot_is_read_once_completely_written() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join("Screenshot_0000.bmp");
+        let bmp = bmp();
+        let (start, end) = bmp.split_at(bmp.len() / 2);
+        std::fs::write(&path, start).unwrap();
+
+        let writer = thread::spawn({
+            let path = path.clone();
+            let end = end.to_vec();
+            move || {
+                thread::sleep(Duration::from_millis(300));
+                let mut file = OpenOptions::new().append(true).open(path).unwrap();
+                file.write_all(&end).unwrap();
+            }
+        });
+        wait_until_complete(&path, Duration::from_secs(5)).unwrap();
+        writer.join().unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), bmp);
+    }
+
+    #[test]
+    fn screenshot_without_size_in_its_header_is_read_once_settled() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join("Screenshot_0000.bmp");
+        let mut bmp = bmp();
+        bmp[2..6].copy_from_slice(&[0; 4]);
+        let (start, end) = bmp.split_at(bmp.len() / 2);
+        std::fs::write(&path, start).unwrap();
+
+        let writer = thread::spawn({
+            let path = path.clone();
+            let end = end.to_vec();
+            move || {
+                thread::sleep(Duration::from_millis(100));
+                let mut file = OpenOptions::new().append(true).open(path).unwrap();
+                file.write_all(&end).unwrap();
+            }
+        });
+        wait_until_complete(&path, Duration::from_secs(5)).unwrap();
+        let size = std::fs::metadata(&path).unwrap().len();
+        writer.join().unwrap();
+
+        assert_eq!(size, bmp.len() as u64);
+    }
+
+    #[test]
+    fn incomplete_screenshot_is_reported() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join("Screenshot_0000.bmp");
+        let bmp = bmp();
+        std::fs::write(&path, &bmp[..bmp.len() / 2]).unwrap();
+
+        let error = wait_until_complete(&path, Duration::from_millis(300)).unwrap_err();
+
+        assert_eq!(
+            error.downcast_ref::<IncompleteScreenshot>(),
+            Some(&IncompleteScreenshot {
+                path,
+                size: bmp.len() as u64 / 2,
+                expected: Some(bmp.len() as u64),
+            })
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use ed_journals::logs::content::{
+    log_event_content::start_jump_event::StartJumpType, LogEventContent,
+};
+use serde::{Deserialize, Serialize};
+
+/// What the player is doing, as far as the journal tells.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FlightState {
+    #[default]
+    Unknown,
+    NormalSpace,
+    Supercruise,
+    /// From the start of the jump countdown to the arrival.
+    Hyperspace,
+    Docked,
+    Landed,
+}
+
+/// The game state when a screenshot was taken, built from the journal.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CaptureContext {
+    pub commander: Option<String>,
+    pub ship: Option<String>,
+    pub ship_name: Option<String>,
+    pub system: Option<String>,
+    pub system_address: Option<u64>,
+    /// Coordinates of the system, in light years from Sol.
+    pub star_pos: Option<[f32; 3]>,
+    pub body: Option<String>,
+    pub station: Option<String>,
+    pub state: FlightState,
+    /// The music played by the game, which tells some of the menus.
+    pub music_track: Option<String>,
+}
+
+impl CaptureContext {
+    /// Follow the game state with a new journal event.
+    pub fn update(&mut self, event: &LogEventContent) {
+        match event {
+            // A new journal, everything will be logged again
+            LogEventContent::FileHeader(_) => *self = Self::default(),
+            LogEventContent::Commander(commander) => {
+                self.commander = Some(commander.name.clone());
+            }
+            LogEventContent::LoadGame(load_game) => {
+                self.commander = Some(load_game.commander.clone());
+                if let Some(ship_info) = &load_game.ship_info {
+                    self.ship = Some(ship_info.ship.to_string());
+                    self.ship_name = Some(ship_info.ship_name.clone());
+                }
+            }
+            LogEventContent::Loadout(loadout) => {
+                self.ship = Some(loadout.ship.to_string());
+                self.ship_name = Some(loadout.ship_name.clone());
+            }
+            LogEventContent::Location(location) => {
+                let info = &location.location_info;
+                self.enter_system(&info.star_system, info.system_address);
+                self.star_pos = Some(info.star_pos);
+                self.body = Some(info.body.clone());
+                self.station = None;
+                self.state = if location.docked {
+                    FlightState::Docked
+                } else {
+                    FlightState::NormalSpace
+                };
+            }
+            LogEventContent::FSDJump(jump) => {
+                let info = &jump.system_info;
+                self.enter_system(&info.star_system, info.system_address);
+                self.star_pos = Some(info.star_pos);
+                self.body = Some(info.body.clone());
+                self.station = None;
+                self.state = FlightState::Supercruise;
+            }
+            LogEventContent::StartJump(jump) => {
+                if let StartJumpType::Hyperspace { .. } = jump.jump {
+                    self.state = FlightState::Hyperspace;
+                }
+            }
+            LogEventContent::SupercruiseEntry(entry) => {
+                self.enter_system(&entry.star_system, entry.system_address);
+                self.body = None;
+                self.station = None;
+                self.state = FlightState::Supercruise;
+            }
+            LogEventContent::SupercruiseExit(exit) => {
+                self.enter_system(&exit.star_system, exit.system_address);
+                self.body = Some(exit.body.clone());
+                self.state = FlightState::NormalSpace;
+            }
+            LogEventContent::ApproachBody(approach) => {
+                // The fields of this event are private
+                if let Ok(serde_json::Value::Object(approach)) = serde_json::to_value(approach) {
+                    if let Some(body) = approach.get("Body").and_then(|b| b.as_str()) {
+                        self.body = Some(body.to_string());
+                    }
+                }
+            }
+            LogEventContent::LeaveBody(_) => {
+                self.body = None;
+            }
+            LogEventContent::Docked(docked) => {
+                self.enter_system(&docked.star_system, docked.system_address);
+                self.station = Some(docked.station_name.clone());
+                self.state = FlightState::Docked;
+            }
+            LogEventContent::Undocked(_) => {
+                self.station = None;
+                self.state = FlightState::NormalSpace;
+            }
+            LogEventContent::Touchdown(touchdown) => {
+                self.enter_system(&touchdown.star_system, touchdown.system_address);
+                self.body = Some(touchdown.body.clone());
+                self.state = FlightState::Landed;
+            }
+            LogEventContent::Liftoff(liftoff) => {
+                self.enter_system(&liftoff.star_system, liftoff.system_address);
+                self.body = Some(liftoff.body.clone());
+                self.state = FlightState::NormalSpace;
+            }
+            LogEventContent::Music(music) => {
+                self.music_track = Some(music.music_track.clone());
+            }
+            LogEventContent::Screenshot(screenshot) => {
+                if let Some(system) = &screenshot.system {
+                    if self.system.as_ref() != Some(system) {
+                        // The event doesn't tell the address of another system
+                        self.system_address = None;
+                        self.star_pos = None;
+                        self.system = Some(system.clone());
+                    }
+                }
+                if let Some(body) = &screenshot.body {
+                    self.body = Some(body.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn enter_system(&mut self, system: &str, system_address: u64) {
+        if self.system_address != Some(system_address) {
+            // The coordinates are only known from the location and jump events
+            self.star_pos = None;
+        }
+        self.system = Some(system.to_string());
+        self.system_address = Some(system_address);
+    }
+}
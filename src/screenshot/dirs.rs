@@ -0,0 +1,191 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+const ED_STEAM_APP_ID: &str = "359320";
+
+/// The directories the game writes to, auto-detected unless overridden.
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct GameDirs {
+    pub journal_dir: Option<PathBuf>,
+    pub screenshot_dir: Option<PathBuf>,
+}
+
+impl GameDirs {
+    pub fn journal_dir(&self) -> Result<PathBuf> {
+        match &self.journal_dir {
+            Some(dir) => Ok(dir.clone()),
+            None => default_journal_dir(),
+        }
+    }
+
+    pub fn screenshot_dir(&self) -> Result<PathBuf> {
+        match &self.screenshot_dir {
+            Some(dir) => Ok(dir.clone()),
+            None => screenshot_dir_near(self.journal_dir().ok().as_deref()),
+        }
+    }
+}
+
+/// The directory where the game writes its journal files.
+pub fn default_journal_dir() -> Result<PathBuf> {
+    ed_journals::journal::auto_detect_journal_path()
+        .or_else(|| {
+            proton_user_dirs()
+                .map(|user_dir| saved_games_dir(&user_dir))
+                .find(|dir| dir.is_dir())
+        })
+        .context("Failed to find the journal directory")
+}
+
+/// The directory where the game writes its screenshots.
+pub fn default_screenshot_dir() -> Result<PathBuf> {
+    screenshot_dir_near(default_journal_dir().ok().as_deref())
+}
+
+/// The screenshot directory of the player whose journals are in
+/// `journal_dir`, if known.
+fn screenshot_dir_near(journal_dir: Option<&Path>) -> Result<PathBuf> {
+    // Under Proton, the pictures are in the Wine prefix next to the journals
+    #[cfg(not(windows))]
+    {
+        let proton_dir = journal_dir
+            .and_then(windows_user_dir)
+            .into_iter()
+            .chain(proton_user_dirs())
+            .map(|user_dir| pictures_dir(&user_dir.join("Pictures")))
+            .find(|dir| dir.is_dir());
+        if let Some(dir) = proton_dir {
+            return Ok(dir);
+        }
+    }
+
+    let picture_dir = directories::UserDirs::new()
+        .context("Failed to find home directory")?
+        .picture_dir()
+        .context("Failed to find picture directory")?
+        .to_owned();
+    #[cfg(windows)]
+    let _ = journal_dir;
+    Ok(pictures_dir(&picture_dir))
+}
+
+/// The Windows user directory of a journal directory, also in a Wine prefix.
+pub(crate) fn windows_user_dir(journal_dir: &Path) -> Option<PathBuf> {
+    // <user>/Saved Games/Frontier Developments/Elite Dangerous
+    let user_dir = journal_dir.ancestors().nth(3)?;
+    user_dir
+        .join("AppData")
+        .is_dir()
+        .then(|| user_dir.to_owned())
+}
+
+fn saved_games_dir(user_dir: &Path) -> PathBuf {
+    user_dir
+        .join("Saved Games")
+        .join("Frontier Developments")
+        .join("Elite Dangerous")
+}
+
+fn pictures_dir(picture_dir: &Path) -> PathBuf {
+    picture_dir
+        .join("Frontier Developments")
+        .join("Elite Dangerous")
+}
+
+/// The Windows user directories in the usual Steam Proton prefixes.
+fn proton_user_dirs() -> impl Iterator<Item = PathBuf> {
+    directories::UserDirs::new()
+        .into_iter()
+        .flat_map(|dirs| proton_user_dirs_in(dirs.home_dir()))
+}
+
+fn proton_user_dirs_in(home: &Path) -> impl Iterator<Item = PathBuf> {
+    let steam_dirs = [
+        ".local/share/Steam",
+        ".steam/steam",
+        ".var/app/com.valvesoftware.Steam/.local/share/Steam",
+    ];
+    let home = home.to_owned();
+    steam_dirs.into_iter().map(move |steam_dir| {
+        home.join(steam_dir)
+            .join("steamapps/compatdata")
+            .join(ED_STEAM_APP_ID)
+            .join("pfx/drive_c/users/steamuser")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A Proton prefix in a fake home directory, with the game folders.
+    fn proton_prefix(home: &Path) -> PathBuf {
+        let user_dir = proton_user_dirs_in(home).next().unwrap();
+        std::fs::create_dir_all(user_dir.join("AppData/Local")).unwrap();
+        std::fs::create_dir_all(saved_games_dir(&user_dir)).unwrap();
+        std::fs::create_dir_all(pictures_dir(&user_dir.join("Pictures"))).unwrap();
+        user_dir
+    }
+
+    #[test]
+    fn proton_prefixes_are_in_the_steam_libraries() {
+        let dirs: Vec<_> = proton_user_dirs_in(Path::new("/home/cmdr")).collect();
+
+        assert_eq!(
+            dirs,
+            [
+                ".local/share/Steam",
+                ".steam/steam",
+                ".var/app/com.valvesoftware.Steam/.local/share/Steam"
+            ]
+            .map(|steam| Path::new("/home/cmdr")
+                .join(steam)
+                .join("steamapps/compatdata/359320/pfx/drive_c/users/steamuser"))
+        );
+    }
+
+    #[test]
+    fn user_dir_is_found_from_the_journals_of_a_prefix() {
+        let home = tempfile::tempdir().unwrap();
+        let user_dir = proton_prefix(home.path());
+
+        assert_eq!(
+            windows_user_dir(&saved_games_dir(&user_dir)),
+            Some(user_dir)
+        );
+    }
+
+    #[test]
+    fn custom_prefix_is_followed_from_its_journals() {
+        let root = tempfile::tempdir().unwrap();
+        let user_dir = root.path().join("games/elite/drive_c/users/cmdr");
+        std::fs::create_dir_all(user_dir.join("AppData")).unwrap();
+        std::fs::create_dir_all(pictures_dir(&user_dir.join("Pictures"))).unwrap();
+        let dirs = GameDirs {
+            journal_dir: Some(saved_games_dir(&user_dir)),
+            screenshot_dir: None,
+        };
+
+        #[cfg(not(windows))]
+        assert_eq!(
+            dirs.screenshot_dir().unwrap(),
+            pictures_dir(&user_dir.join("Pictures"))
+        );
+        assert_eq!(
+            windows_user_dir(&dirs.journal_dir().unwrap()),
+            Some(user_dir)
+        );
+    }
+
+    #[test]
+    fn journals_outside_of_a_user_dir_have_none() {
+        let root = tempfile::tempdir().unwrap();
+        let journal_dir = root.path().join("journals");
+        std::fs::create_dir_all(&journal_dir).unwrap();
+
+        assert_eq!(windows_user_dir(&journal_dir), None);
+        assert_eq!(windows_user_dir(Path::new("Journal")), None);
+    }
+}
@@ -0,0 +1,92 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+/// The file the game rewrites with its current state, next to the journals.
+const STATUS_FILE: &str = "Status.json";
+
+const FLAG_FSD_CHARGING: u64 = 1 << 17;
+const FLAG_FSD_JUMP: u64 = 1 << 30;
+
+/// What the game shows, from the `Status.json` file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GameStatus {
+    /// False in the main menu, where the game writes no flags.
+    pub in_game: bool,
+    /// The frame shift drive is charging or jumping.
+    pub fsd_jump: bool,
+    pub gui_focus: GuiFocus,
+}
+
+/// The screen or panel having the focus.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GuiFocus {
+    #[default]
+    NoFocus,
+    InternalPanel,
+    ExternalPanel,
+    CommsPanel,
+    RolePanel,
+    StationServices,
+    GalaxyMap,
+    SystemMap,
+    Orrery,
+    Fss,
+    Saa,
+    Codex,
+    Other(u64),
+}
+
+impl From<u64> for GuiFocus {
+    fn from(value: u64) -> Self {
+        match value {
+            0 => GuiFocus::NoFocus,
+            1 => GuiFocus::InternalPanel,
+            2 => GuiFocus::ExternalPanel,
+            3 => GuiFocus::CommsPanel,
+            4 => GuiFocus::RolePanel,
+            5 => GuiFocus::StationServices,
+            6 => GuiFocus::GalaxyMap,
+            7 => GuiFocus::SystemMap,
+            8 => GuiFocus::Orrery,
+            9 => GuiFocus::Fss,
+            10 => GuiFocus::Saa,
+            11 => GuiFocus::Codex,
+            other => GuiFocus::Other(other),
+        }
+    }
+}
+
+impl GameStatus {
+    /// Read the status file of a journal directory, if the game wrote one.
+    pub fn read(journal_dir: &Path) -> Result<Option<Self>> {
+        let path = journal_dir.join(STATUS_FILE);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::parse(&content)
+            .map(Some)
+            .with_context(|| format!("Invalid status in {}", path.display()))
+    }
+
+    /// Parse the content of a `Status.json` file.
+    pub fn parse(json: &str) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(json)?;
+        let flags = value.get("Flags").and_then(|flags| flags.as_u64());
+        // The flags are zero on foot, only their absence tells the main menu
+        let in_game = flags.is_some();
+        let fsd_jump = flags.unwrap_or_default() & (FLAG_FSD_CHARGING | FLAG_FSD_JUMP) != 0;
+        let gui_focus = value
+            .get("GuiFocus")
+            .and_then(|focus| focus.as_u64())
+            .unwrap_or_default()
+            .into();
+        Ok(Self {
+            in_game,
+            fsd_jump,
+            gui_focus,
+        })
+    }
+}
@@ -0,0 +1,56 @@
+use std::path::Path;
+
+use anyhow::Result;
+
+#[cfg(any(windows, target_os = "linux"))]
+use super::bindings::ScreenshotKeys;
+
+#[cfg(windows)]
+mod win32;
+#[cfg(target_os = "linux")]
+mod x11;
+
+#[cfg(windows)]
+pub use self::win32::SendInputTrigger;
+#[cfg(target_os = "linux")]
+pub use self::x11::XTestTrigger;
+
+#[cfg(any(windows, target_os = "linux"))]
+const ED_WINDOW_TITLE: &str = "Elite - Dangerous (CLIENT)";
+
+/// Something able to make Elite Dangerous take a screenshot.
+///
+/// The trigger only has to ask for the screenshot, the resulting file is
+/// picked up from the journal by the [`Watcher`](super::Watcher).
+pub trait ScreenshotTrigger: Send {
+    fn trigger(&mut self, high_res: bool) -> Result<()>;
+
+    /// Whether the game window is minimized, when the trigger can tell.
+    fn game_minimized(&mut self) -> Result<bool> {
+        Ok(false)
+    }
+}
+
+/// The trigger for the current platform, using the key bindings of the
+/// player whose journals are in `journal_dir`.
+pub fn platform_default(journal_dir: &Path) -> Result<Box<dyn ScreenshotTrigger>> {
+    #[cfg(windows)]
+    {
+        Ok(Box::new(SendInputTrigger::new(ScreenshotKeys::load(
+            journal_dir,
+        )?)))
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        Ok(Box::new(XTestTrigger::try_new(ScreenshotKeys::load(
+            journal_dir,
+        )?)?))
+    }
+
+    #[cfg(not(any(windows, target_os = "linux")))]
+    {
+        let _ = journal_dir;
+        anyhow::bail!("No screenshot trigger is available on this platform.")
+    }
+}
@@ -2,25 +2,33 @@ use std::{mem::size_of, thread, time::Duration};
 
 use anyhow::{bail, Context, Result};
 use windows::{
-    core::{w, PCWSTR},
+    core::{HSTRING, PCWSTR},
     Win32::UI::{
         Input::KeyboardAndMouse::{
             SendInput, INPUT, INPUT_0, INPUT_KEYBOARD, KEYBDINPUT, KEYBD_EVENT_FLAGS,
-            KEYEVENTF_KEYUP, VIRTUAL_KEY,
+            KEYEVENTF_EXTENDEDKEY, KEYEVENTF_KEYUP, VIRTUAL_KEY,
         },
         WindowsAndMessaging::{
-            BringWindowToTop, FindWindowW, GetForegroundWindow, SetForegroundWindow,
+            BringWindowToTop, FindWindowW, GetForegroundWindow, IsIconic, SetForegroundWindow,
         },
     },
 };
 
-fn keyscan_input(wscan: u16, dwflag: u32) -> INPUT {
+use super::{ScreenshotTrigger, ED_WINDOW_TITLE};
+use crate::screenshot::bindings::{Key, ScreenshotKeys};
+
+fn keyscan_input(key: Key, dwflag: u32) -> INPUT {
+    let dwflag = if key.extended {
+        dwflag | KEYEVENTF_EXTENDEDKEY.0
+    } else {
+        dwflag
+    };
     INPUT {
         r#type: INPUT_KEYBOARD,
         Anonymous: INPUT_0 {
             ki: KEYBDINPUT {
                 wVk: VIRTUAL_KEY(0),
-                wScan: wscan,
+                wScan: key.scancode,
                 dwFlags: KEYBD_EVENT_FLAGS(dwflag),
                 time: 0,
                 dwExtraInfo: 0,
@@ -29,13 +37,34 @@ fn keyscan_input(wscan: u16, dwflag: u32) -> INPUT {
     }
 }
 
-const KEYSCAN_F10: u16 = 0x44;
-const KEYSCAN_LALT: u16 = 0x38;
+/// Focuses the game window and sends the screenshot keys with `SendInput`.
+pub struct SendInputTrigger {
+    keys: ScreenshotKeys,
+}
+
+impl SendInputTrigger {
+    pub fn new(keys: ScreenshotKeys) -> Self {
+        Self { keys }
+    }
+}
+
+impl ScreenshotTrigger for SendInputTrigger {
+    fn trigger(&mut self, high_res: bool) -> Result<()> {
+        request_screenshot(&self.keys.combo(high_res)?.keys())
+    }
+
+    fn game_minimized(&mut self) -> Result<bool> {
+        unsafe {
+            let ed_window = FindWindowW(PCWSTR::null(), &HSTRING::from(ED_WINDOW_TITLE));
+            Ok(ed_window.0 != 0 && IsIconic(ed_window).as_bool())
+        }
+    }
+}
 
-pub fn request_screenshot(high_res: bool) -> Result<()> {
+fn request_screenshot(keys: &[Key]) -> Result<()> {
     unsafe {
         let active_window = GetForegroundWindow();
-        let ed_window = FindWindowW(PCWSTR::null(), w!("Elite - Dangerous (CLIENT)"));
+        let ed_window = FindWindowW(PCWSTR::null(), &HSTRING::from(ED_WINDOW_TITLE));
         if ed_window.0 == 0 {
             bail!("Elite Dangerous does not appear to be running.");
         }
@@ -46,12 +75,6 @@ pub fn request_screenshot(high_res: bool) -> Result<()> {
 
         BringWindowToTop(ed_window).context("Failed to bring Elite Dangerous to the top.")?;
 
-        let keys = if high_res {
-            vec![KEYSCAN_LALT, KEYSCAN_F10]
-        } else {
-            vec![KEYSCAN_F10]
-        };
-
         thread::sleep(Duration::from_millis(60));
         let sent = SendInput(
             &keys
@@ -0,0 +1,227 @@
+use std::{thread, time::Duration};
+
+use anyhow::{bail, Context, Result};
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        xproto::{
+            Atom, AtomEnum, ClientMessageEvent, ConnectionExt, EventMask, Window, KEY_PRESS_EVENT,
+            KEY_RELEASE_EVENT,
+        },
+        xtest::ConnectionExt as _,
+    },
+    rust_connection::RustConnection,
+    wrapper::ConnectionExt as _,
+    CURRENT_TIME, NONE,
+};
+
+use super::{ScreenshotTrigger, ED_WINDOW_TITLE};
+use crate::screenshot::bindings::{Key, ScreenshotKeys};
+
+/// Class of the game window when running through Proton/Wine.
+const ED_WINDOW_CLASS: &str = "elitedangerous64.exe";
+
+/// X keycodes are the evdev keycodes offset by 8.
+const X_KEYCODE_OFFSET: u16 = 8;
+
+x11rb::atom_manager! {
+    Atoms: AtomsCookie {
+        _NET_ACTIVE_WINDOW,
+        _NET_CLIENT_LIST,
+        _NET_WM_NAME,
+        _NET_WM_STATE,
+        _NET_WM_STATE_HIDDEN,
+        UTF8_STRING,
+    }
+}
+
+/// Focuses the game window and sends the screenshot keys with the XTEST
+/// extension.
+///
+/// This works with the game running through Proton, including under a
+/// Wayland session as the game then runs in XWayland.
+pub struct XTestTrigger {
+    conn: RustConnection,
+    root: Window,
+    atoms: Atoms,
+    keys: ScreenshotKeys,
+}
+
+impl XTestTrigger {
+    pub fn try_new(keys: ScreenshotKeys) -> Result<Self> {
+        let (conn, screen) = x11rb::connect(None).context("Failed to connect to the X server")?;
+        let root = conn.setup().roots[screen].root;
+        let atoms = Atoms::new(&conn)?.reply()?;
+        conn.xtest_get_version(2, 2)?
+            .reply()
+            .context("The X server does not support the XTEST extension")?;
+        Ok(Self {
+            conn,
+            root,
+            atoms,
+            keys,
+        })
+    }
+
+    fn active_window(&self) -> Result<Option<Window>> {
+        let reply = self
+            .conn
+            .get_property(
+                false,
+                self.root,
+                self.atoms._NET_ACTIVE_WINDOW,
+                AtomEnum::WINDOW,
+                0,
+                1,
+            )?
+            .reply()?;
+        Ok(reply
+            .value32()
+            .and_then(|mut v| v.next())
+            .filter(|w| *w != NONE))
+    }
+
+    fn activate(&self, window: Window) -> Result<()> {
+        // Source indication 2 is "pager", which window managers are less
+        // likely to refuse than a regular application.
+        let event = ClientMessageEvent::new(
+            32,
+            window,
+            self.atoms._NET_ACTIVE_WINDOW,
+            [2, CURRENT_TIME, 0, 0, 0],
+        );
+        self.conn.send_event(
+            false,
+            self.root,
+            EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+            event,
+        )?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    fn find_ed_window(&self) -> Result<Option<Window>> {
+        let reply = self
+            .conn
+            .get_property(
+                false,
+                self.root,
+                self.atoms._NET_CLIENT_LIST,
+                AtomEnum::WINDOW,
+                0,
+                u32::MAX,
+            )?
+            .reply()?;
+        let windows: Vec<Window> = match reply.value32() {
+            Some(windows) => windows.collect(),
+            // No EWMH window manager, look at the top level windows instead
+            None => self.conn.query_tree(self.root)?.reply()?.children,
+        };
+        for window in windows {
+            if self.is_ed_window(window)? {
+                return Ok(Some(window));
+            }
+        }
+        Ok(None)
+    }
+
+    fn is_ed_window(&self, window: Window) -> Result<bool> {
+        let name = self.string_property(window, self.atoms._NET_WM_NAME, self.atoms.UTF8_STRING)?;
+        if name.as_deref() == Some(ED_WINDOW_TITLE) {
+            return Ok(true);
+        }
+        let name =
+            self.string_property(window, AtomEnum::WM_NAME.into(), AtomEnum::STRING.into())?;
+        if name.as_deref() == Some(ED_WINDOW_TITLE) {
+            return Ok(true);
+        }
+        // WM_CLASS is two consecutive null terminated strings: instance and class
+        let class =
+            self.string_property(window, AtomEnum::WM_CLASS.into(), AtomEnum::STRING.into())?;
+        Ok(class.is_some_and(|class| {
+            class
+                .split('\0')
+                .any(|c| c.eq_ignore_ascii_case(ED_WINDOW_CLASS))
+        }))
+    }
+
+    fn is_hidden(&self, window: Window) -> Result<bool> {
+        let reply = self
+            .conn
+            .get_property(
+                false,
+                window,
+                self.atoms._NET_WM_STATE,
+                AtomEnum::ATOM,
+                0,
+                1024,
+            )?
+            .reply()?;
+        Ok(reply
+            .value32()
+            .is_some_and(|mut states| states.any(|s| s == self.atoms._NET_WM_STATE_HIDDEN)))
+    }
+
+    fn string_property(
+        &self,
+        window: Window,
+        property: Atom,
+        type_: Atom,
+    ) -> Result<Option<String>> {
+        let reply = self
+            .conn
+            .get_property(false, window, property, type_, 0, 1024)?
+            .reply()?;
+        if reply.value.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(String::from_utf8_lossy(&reply.value).into_owned()))
+    }
+
+    fn send_keys(&self, keys: &[Key], type_: u8) -> Result<()> {
+        for key in keys {
+            let keycode =
+                u8::try_from(key.evdev() + X_KEYCODE_OFFSET).context("Invalid keycode")?;
+            self.conn
+                .xtest_fake_input(type_, keycode, CURRENT_TIME, self.root, 0, 0, 0)?;
+        }
+        self.conn.sync()?;
+        Ok(())
+    }
+}
+
+impl ScreenshotTrigger for XTestTrigger {
+    fn trigger(&mut self, high_res: bool) -> Result<()> {
+        let active_window = self.active_window()?;
+        let Some(ed_window) = self.find_ed_window()? else {
+            bail!("Elite Dangerous does not appear to be running.");
+        };
+
+        if active_window != Some(ed_window) {
+            self.activate(ed_window)
+                .context("Failed to bring Elite Dangerous to the foreground.")?;
+        }
+
+        let keys = self.keys.combo(high_res)?.keys();
+
+        thread::sleep(Duration::from_millis(60));
+        self.send_keys(&keys, KEY_PRESS_EVENT)
+            .context("Failed to send down keypresses.")?;
+        thread::sleep(Duration::from_millis(60));
+        let keys_up: Vec<_> = keys.into_iter().rev().collect();
+        self.send_keys(&keys_up, KEY_RELEASE_EVENT)
+            .context("Failed to send up keypresses.")?;
+
+        if let Some(active_window) = active_window.filter(|w| *w != ed_window) {
+            let _ = self.activate(active_window);
+        }
+        Ok(())
+    }
+
+    fn game_minimized(&mut self) -> Result<bool> {
+        match self.find_ed_window()? {
+            Some(ed_window) => self.is_hidden(ed_window),
+            None => Ok(false),
+        }
+    }
+}
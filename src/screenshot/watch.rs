@@ -1,42 +1,228 @@
-use std::sync::mpsc::{Receiver, Sender};
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+};
 
 use anyhow::{Context, Result};
-use ed_journals::logs::content::{
-    log_event_content::screenshot_event::ScreenshotEvent, LogEventContent,
+use chrono::{DateTime, Utc};
+use ed_journals::logs::{
+    content::{log_event_content::screenshot_event::ScreenshotEvent, LogEvent, LogEventContent},
+    LogDir, LogFile,
 };
+use notify::{RecursiveMode, Watcher};
+
+use super::{CaptureContext, GameStatus};
+
+/// A screenshot logged in the journal.
+pub struct JournalScreenshot {
+    pub timestamp: DateTime<Utc>,
+    pub event: ScreenshotEvent,
+    /// The game state when the screenshot was logged.
+    pub context: CaptureContext,
+}
+
+/// The game state followed by the watcher.
+#[derive(Debug, Clone, Default)]
+pub struct GameState {
+    pub context: CaptureContext,
+    /// From the `Status.json` file, if the game wrote one.
+    pub status: Option<GameStatus>,
+}
+
+/// Something happened in the game.
+#[derive(Debug, Clone)]
+pub enum GameEvent {
+    /// The listener was just added.
+    Subscribed,
+    Journal(Box<LogEvent>),
+    /// The `Status.json` file changed.
+    Status,
+}
+
+/// Called with every game event and the state after it, removed when it
+/// returns false.
+pub type Listener = Box<dyn FnMut(&GameEvent, &GameState) -> bool + Send>;
+
+/// What the watcher thread shares with the [`super::Watcher`].
+#[derive(Default)]
+pub struct Shared {
+    listeners: Vec<Listener>,
+    pub state: GameState,
+}
 
-pub struct Exit;
+impl Shared {
+    pub fn subscribe(&mut self, mut listener: Listener) {
+        if listener(&GameEvent::Subscribed, &self.state) {
+            self.listeners.push(listener);
+        }
+    }
+
+    fn notify(&mut self, event: &GameEvent) {
+        let Self { listeners, state } = self;
+        listeners.retain_mut(|listener| listener(event, state));
+    }
+}
+
+enum Message {
+    /// Something changed in the journal directory: new lines in the current
+    /// journal, or a new journal when the game restarts.
+    Changed,
+    Exit,
+}
+
+/// Stops the journal watcher thread when dropped.
+pub struct Handle {
+    control_tx: Sender<Message>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        // The thread may already be gone if it failed
+        let _ = self.control_tx.send(Message::Exit);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("The screenshot watcher thread panicked");
+            }
+        }
+    }
+}
 
-pub fn watch_screenshots() -> Result<(Receiver<ScreenshotEvent>, Sender<Exit>)> {
+pub fn watch_screenshots(
+    journal_dir: PathBuf,
+    shared: Arc<Mutex<Shared>>,
+) -> Result<(Receiver<JournalScreenshot>, Handle)> {
     let (tx, rx) = std::sync::mpsc::channel();
-    let (exit_tx, exit_rx) = std::sync::mpsc::channel();
-    let journal_dir = ed_journals::journal::auto_detect_journal_path()
-        .context("Failed to find the journal directory")?;
-    let journals = ed_journals::logs::LogDir::new(journal_dir);
-    let reader = journals
-        .journal_logs_newest_first()?
-        .first()
-        .context("No journal files")?
-        .create_live_blocking_reader()
-        .context("Failed to read the journal file")?;
-
-    std::thread::spawn(move || {
-        for event in reader {
-            // bug, won't stop if no event arrive in the journal
-            if exit_rx.try_recv().is_ok() {
-                return;
+    let (control_tx, control_rx) = std::sync::mpsc::channel();
+
+    let changed_tx = control_tx.clone();
+    let mut dir_watcher = notify::recommended_watcher(move |_| {
+        let _ = changed_tx.send(Message::Changed);
+    })?;
+    dir_watcher
+        .watch(&journal_dir, RecursiveMode::NonRecursive)
+        .context("Failed to watch the journal directory")?;
+
+    let journals = LogDir::new(journal_dir.clone());
+    // The game may not have written a journal yet, it is followed once it does
+    let mut current = match newest_journal(&journals)? {
+        Some(journal) => {
+            let reader = journal
+                .create_blocking_reader()
+                .context("Failed to read the journal file")?;
+            Some((journal, reader))
+        }
+        None => {
+            log::info!("No journal file yet, waiting for the game to write one");
+            None
+        }
+    };
+
+    let thread = std::thread::spawn(move || {
+        let _dir_watcher = dir_watcher;
+        loop {
+            for event in current.iter_mut().flat_map(|(_, reader)| reader) {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(err) => {
+                        log::error!("Error reading journal event: {:?}", err);
+                        continue;
+                    }
+                };
+                let mut shared = shared.lock().unwrap();
+                shared.state.context.update(&event.content);
+                let event = GameEvent::Journal(Box::new(event));
+                shared.notify(&event);
+                let GameEvent::Journal(event) = event else {
+                    continue;
+                };
+                let LogEvent {
+                    timestamp,
+                    content: LogEventContent::Screenshot(screenshot_event),
+                } = *event
+                else {
+                    continue;
+                };
+                let screenshot = JournalScreenshot {
+                    timestamp,
+                    event: screenshot_event,
+                    context: shared.state.context.clone(),
+                };
+                drop(shared);
+                if tx.send(screenshot).is_err() {
+                    return;
+                }
             }
-            match event {
-                Ok(event) => {
-                    if let LogEventContent::Screenshot(screenshot_event) = event.content {
-                        tx.send(screenshot_event).unwrap();
+
+            update_status(&shared, &journal_dir);
+
+            match newest_journal(&journals) {
+                Ok(Some(newest))
+                    if current
+                        .as_ref()
+                        .map_or(true, |(journal, _)| newest > *journal) =>
+                {
+                    match newest.create_blocking_reader() {
+                        Ok(reader) => {
+                            log::info!(
+                                "Following the new journal file from {}, part {}",
+                                newest.date_time(),
+                                newest.part()
+                            );
+                            current = Some((newest, reader));
+                            continue;
+                        }
+                        Err(err) => {
+                            log::error!("Failed to read the new journal file: {:?}", err);
+                        }
                     }
                 }
+                Ok(_) => {}
                 Err(err) => {
-                    log::error!("Error reading journal event: {:?}", err);
+                    log::error!("Failed to list the journal files: {:?}", err);
+                }
+            }
+
+            if !matches!(control_rx.recv(), Ok(Message::Changed)) {
+                return;
+            }
+            // Coalesce the pending changes, the whole journal is read anyway
+            for message in control_rx.try_iter() {
+                if let Message::Exit = message {
+                    return;
                 }
             }
         }
     });
-    Ok((rx, exit_tx))
+    Ok((
+        rx,
+        Handle {
+            control_tx,
+            thread: Some(thread),
+        },
+    ))
+}
+
+fn update_status(shared: &Mutex<Shared>, journal_dir: &Path) {
+    let status = match GameStatus::read(journal_dir) {
+        Ok(status) => status,
+        Err(err) => {
+            // The game may be writing it
+            log::debug!("Failed to read the game status: {:?}", err);
+            return;
+        }
+    };
+    let mut shared = shared.lock().unwrap();
+    if shared.state.status != status {
+        shared.state.status = status;
+        shared.notify(&GameEvent::Status);
+    }
+}
+
+fn newest_journal(journals: &LogDir) -> Result<Option<LogFile>> {
+    Ok(journals.journal_logs_newest_first()?.into_iter().next())
 }
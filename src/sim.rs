@@ -0,0 +1,200 @@
+//! A simulated game, to exercise the capture loop without Elite Dangerous.
+//!
+//! [`SimGame`] owns a journal directory and a screenshot directory, and its
+//! [`SimTrigger`] behaves like the game when the screenshot key is pressed:
+//! it writes a bitmap in the screenshot directory and logs a `Screenshot`
+//! event in the journal.
+
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, SubsecRound, Utc};
+use ed_journals::logs::content::{
+    log_event_content::{file_header_event::FileHeaderEvent, screenshot_event::ScreenshotEvent},
+    LogEvent, LogEventContent,
+};
+use image::{ImageFormat, Rgb, RgbImage};
+
+use crate::screenshot::{ScreenshotTrigger, Watcher};
+
+const SIZE: (u32, u32) = (64, 36);
+const HIGH_RES_SIZE: (u32, u32) = (128, 72);
+
+#[derive(Clone)]
+pub struct SimGame {
+    journal_dir: PathBuf,
+    screenshot_dir: PathBuf,
+    journal_file: Arc<Mutex<PathBuf>>,
+    minimized: Arc<AtomicBool>,
+}
+
+impl SimGame {
+    /// Create the game directories in `root`, with a fresh journal file.
+    pub fn new(root: &Path) -> Result<Self> {
+        let game = Self::installed(root)?;
+        game.restart()?;
+        Ok(game)
+    }
+
+    /// Create the game directories in `root`, without any journal, like a
+    /// fresh install.
+    pub fn installed(root: &Path) -> Result<Self> {
+        let journal_dir = root.join("Journal");
+        let screenshot_dir = root.join("Screenshots");
+        std::fs::create_dir_all(&journal_dir)?;
+        std::fs::create_dir_all(&screenshot_dir)?;
+        Ok(Self {
+            journal_dir,
+            screenshot_dir,
+            journal_file: Default::default(),
+            minimized: Default::default(),
+        })
+    }
+
+    /// Start writing a new journal file, like the game does when (re)started.
+    pub fn restart(&self) -> Result<()> {
+        let now = chrono::Local::now().format("%Y-%m-%dT%H%M%S");
+        let journal_file = (1..)
+            .map(|part| {
+                self.journal_dir
+                    .join(format!("Journal.{}.{:02}.log", now, part))
+            })
+            .find(|path| !path.exists())
+            .expect("Ran out of journal names");
+        *self.journal_file.lock().unwrap() = journal_file;
+        self.append_event(LogEventContent::FileHeader(FileHeaderEvent {
+            part: 1,
+            language: "English/UK".to_string(),
+            odyssey: true,
+            game_version: "4.0.0.1800".to_string(),
+            build: "sim".to_string(),
+        }))
+    }
+
+    /// Minimize or restore the game window.
+    pub fn set_minimized(&self, minimized: bool) {
+        self.minimized.store(minimized, Ordering::SeqCst);
+    }
+
+    pub fn journal_dir(&self) -> &Path {
+        &self.journal_dir
+    }
+
+    pub fn screenshot_dir(&self) -> &Path {
+        &self.screenshot_dir
+    }
+
+    /// Log an event in the journal, timestamped now.
+    pub fn append_event(&self, content: LogEventContent) -> Result<()> {
+        self.append_event_at(Utc::now(), content)
+    }
+
+    pub fn append_event_at(
+        &self,
+        timestamp: DateTime<Utc>,
+        content: LogEventContent,
+    ) -> Result<()> {
+        append_event(&self.journal_file.lock().unwrap(), timestamp, content)
+    }
+
+    pub fn trigger(&self) -> SimTrigger {
+        SimTrigger {
+            journal_file: self.journal_file.clone(),
+            screenshot_dir: self.screenshot_dir.clone(),
+            minimized: self.minimized.clone(),
+            system: "Sol".to_string(),
+            body: Some("Earth".to_string()),
+        }
+    }
+
+    /// A watcher taking its screenshots in this game.
+    pub fn watcher(&self) -> Result<Watcher> {
+        Watcher::new(
+            Box::new(self.trigger()),
+            self.journal_dir.clone(),
+            self.screenshot_dir.clone(),
+        )
+    }
+}
+
+pub struct SimTrigger {
+    journal_file: Arc<Mutex<PathBuf>>,
+    screenshot_dir: PathBuf,
+    minimized: Arc<AtomicBool>,
+    system: String,
+    body: Option<String>,
+}
+
+impl SimTrigger {
+    /// Set the location reported in the screenshot events.
+    pub fn with_location(mut self, system: &str, body: Option<&str>) -> Self {
+        self.system = system.to_string();
+        self.body = body.map(str::to_string);
+        self
+    }
+
+    /// The first free file name, like the game does.
+    fn next_filename(&self) -> String {
+        (0..)
+            .map(|i| format!("Screenshot_{:04}.bmp", i))
+            .find(|name| !self.screenshot_dir.join(name).exists())
+            .expect("Ran out of screenshot names")
+    }
+}
+
+impl ScreenshotTrigger for SimTrigger {
+    fn trigger(&mut self, high_res: bool) -> Result<()> {
+        let (width, height) = if high_res { HIGH_RES_SIZE } else { SIZE };
+        let filename = self.next_filename();
+        let image = RgbImage::from_fn(width, height, |x, y| {
+            Rgb([(x * 255 / width) as u8, (y * 255 / height) as u8, 128])
+        });
+        image
+            .save_with_format(self.screenshot_dir.join(&filename), ImageFormat::Bmp)
+            .context("Failed to write the simulated screenshot")?;
+        append_event(
+            &self.journal_file.lock().unwrap(),
+            Utc::now(),
+            LogEventContent::Screenshot(ScreenshotEvent {
+                filename: format!("\\ED_Pictures\\{}", filename),
+                width: width as u16,
+                height: height as u16,
+                system: Some(self.system.clone()),
+                body: self.body.clone(),
+                latitude: None,
+                longitude: None,
+                altitude: None,
+                heading: None,
+            }),
+        )
+    }
+
+    fn game_minimized(&mut self) -> Result<bool> {
+        Ok(self.minimized.load(Ordering::SeqCst))
+    }
+}
+
+fn append_event(
+    journal_file: &Path,
+    timestamp: DateTime<Utc>,
+    content: LogEventContent,
+) -> Result<()> {
+    // Logged to the second, like the game
+    let timestamp = timestamp.trunc_subsecs(0);
+    let event = LogEvent { timestamp, content };
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(journal_file)
+        .context("Failed to open the simulated journal")?;
+    writeln!(file, "{}", serde_json::to_string(&event)?)?;
+    Ok(())
+}
@@ -1,130 +1,864 @@
 use std::{
+    fs::File,
+    io::{ErrorKind, Write},
+    ops::ControlFlow,
     path::{Path, PathBuf},
-    sync::mpsc::{Receiver, Sender},
-    thread,
+    sync::{
+        mpsc::{Receiver, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
     time::{Duration, Instant},
 };
 
-use anyhow::Result;
+use anyhow::{bail, ensure, Context, Result};
+use chrono::{Local, SubsecRound, Utc};
+use ed_journals::logs::content::LogEventContent;
+use image::GenericImageView;
 use log::info;
 
-use crate::screenshot::{Screenshot, Watcher};
+use crate::screenshot::{wait_until_complete, GameEvent, Watcher};
 
-pub struct Exit;
+use self::session::with_copy_number;
+
+mod format;
+mod manifest;
+mod metadata;
+mod pipeline;
+mod rules;
+mod session;
+mod sidecar;
+mod space;
+mod suspension;
+mod template;
+mod transform;
+
+pub use self::format::OutputFormat;
+pub use self::manifest::{
+    Frame, Gap, GapReason, Manifest, Record, Settings, MANIFEST_FILE, MANIFEST_SNAPSHOT_FILE,
+};
+pub use self::metadata::{Metadata, XMP_NAMESPACE};
+pub use self::pipeline::{Backlog, ConversionStatus, Job, Pipeline};
+pub use self::rules::{CaptureRules, Skip};
+pub use self::session::Session;
+pub use self::sidecar::{Sidecar, SIDECAR_VERSION};
+pub use self::space::{frame_size_estimate, free_space, LowSpace, SpaceAction, SpaceGuard};
+pub use self::suspension::Suspension;
+pub use self::template::{Fields, NameTemplate, DEFAULT_SESSION_TEMPLATE, DEFAULT_TEMPLATE};
+pub use self::transform::{AspectRatio, FrameTransform, ResizeFilter, Resolution, SizeMismatch};
+
+/// How often a minimized game window is checked, to resume the captures.
+const WINDOW_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long the game may take to write a high resolution screenshot.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+enum Message {
+    Exit,
+    Pause,
+    Resume,
+    /// The game entered or left a screen not worth capturing.
+    Suspend(Option<Suspension>),
+    /// A journal event matching the capture triggers was logged.
+    Triggered(&'static str),
+}
 
 #[derive(Debug, Clone)]
 pub enum Status {
     Capturing,
     Waiting(Instant),
+    WaitingForEvent,
+    Paused,
+    Suspended(Suspension),
+    /// Paused as a disk is almost full, until resumed.
+    OutOfSpace(LowSpace),
+    /// Stopped by itself, as a disk is full.
+    Stopped(LowSpace),
+}
+
+/// When to take the screenshots of a timelapse.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Schedule {
+    /// At a fixed interval.
+    Interval(Duration),
+    /// After some journal events.
+    Events(EventTriggers),
+}
+
+/// The journal events triggering a screenshot.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct EventTriggers {
+    pub fsd_jump: bool,
+    /// `Docked` and `Undocked`.
+    pub docking: bool,
+    /// `Touchdown` and `Liftoff`.
+    pub landing: bool,
+    pub supercruise_exit: bool,
+    /// `Scan` of a body nobody discovered before.
+    pub new_body_scan: bool,
+    /// Wait after the event, for example for the end of the arrival animation.
+    pub delay: Duration,
+}
+
+impl Default for EventTriggers {
+    fn default() -> Self {
+        Self {
+            fsd_jump: true,
+            docking: false,
+            landing: false,
+            supercruise_exit: false,
+            new_body_scan: false,
+            delay: Duration::from_secs(5),
+        }
+    }
+}
+
+impl EventTriggers {
+    /// The name of the event, if it triggers a screenshot.
+    pub fn trigger(&self, event: &LogEventContent) -> Option<&'static str> {
+        let (enabled, name) = match event {
+            LogEventContent::FSDJump(_) => (self.fsd_jump, "FSDJump"),
+            LogEventContent::Docked(_) => (self.docking, "Docked"),
+            LogEventContent::Undocked(_) => (self.docking, "Undocked"),
+            LogEventContent::Touchdown(_) => (self.landing, "Touchdown"),
+            LogEventContent::Liftoff(_) => (self.landing, "Liftoff"),
+            LogEventContent::SupercruiseExit(_) => (self.supercruise_exit, "SupercruiseExit"),
+            LogEventContent::Scan(scan) => (self.new_body_scan && !scan.was_discovered, "Scan"),
+            _ => (false, ""),
+        };
+        enabled.then_some(name)
+    }
+}
+
+/// Where and how the screenshots are stored.
+#[derive(Debug, Clone)]
+pub struct StoreOptions {
+    pub folder: PathBuf,
+    /// Names the folder of each session, in `folder`.
+    pub session_template: NameTemplate,
+    /// Names the frames, in their session folder.
+    pub template: NameTemplate,
+    pub format: OutputFormat,
+    pub transform: FrameTransform,
+    /// Write the game context in the frames, as EXIF and XMP.
+    pub metadata: bool,
+    /// Write the journal event and context in a JSON file next to each frame.
+    pub sidecar: bool,
+    /// Convert the screenshots and store them in the folder, or leave them
+    /// where the game wrote them.
+    pub organize: bool,
+    pub remove_original: bool,
+    /// Move the removed originals to this folder instead of deleting them.
+    pub trash: Option<PathBuf>,
+    pub space: SpaceGuard,
 }
 
 #[derive(Debug)]
 pub struct TimelapseControl {
-    pub exit_tx: Sender<Exit>,
+    control_tx: Sender<Message>,
     status_rx: Receiver<Status>,
     pub status: Status,
+    paused: bool,
+    session_folder: PathBuf,
+    backlog: Backlog,
+    thread: Option<JoinHandle<()>>,
+    /// Whether it was asked to stop.
+    stopping: bool,
 }
 
 impl TimelapseControl {
     pub fn start(
-        folder: PathBuf,
-        interval: Duration,
+        screenshot: Watcher,
+        schedule: Schedule,
+        rules: CaptureRules,
         high_res: bool,
-        organize: bool,
-        remove_original: bool,
+        store: StoreOptions,
     ) -> Result<Self> {
-        let mut screenshot = crate::screenshot::Watcher::try_new()?;
-        let (exit_tx, exit_rx) = std::sync::mpsc::channel();
+        let (control_tx, control_rx) = std::sync::mpsc::channel();
         let (status_tx, status_rx) = std::sync::mpsc::channel();
-        let start = Instant::now();
-        let mut index = 0;
-        thread::spawn(move || loop {
-            if exit_rx.try_recv().is_ok() {
-                info!("Stopping the timelapse");
-                return;
-            }
-            status_tx.send(Status::Capturing).unwrap();
-            match take_screenshot(
-                &mut screenshot,
-                high_res,
-                organize,
-                remove_original,
-                &folder,
-            ) {
-                Ok(s) => {
-                    log::info!("Screenshot taken: {}", s.display());
-                }
-                Err(e) => {
-                    log::error!("Failed to take screenshot: {}", e);
+
+        let triggers = match &schedule {
+            Schedule::Interval(_) => None,
+            Schedule::Events(triggers) => Some(triggers.clone()),
+        };
+        // The journal is read from its start, ignore the past events. The
+        // events are logged to the second, so those of the start second
+        // could be from before the start too
+        let started = Utc::now().trunc_subsecs(0);
+        let mut suspension = None;
+        let game_tx = control_tx.clone();
+        let auto_pause = rules.auto_pause;
+        screenshot.subscribe(move |event, state| {
+            if auto_pause {
+                let current = Suspension::of(state);
+                if current != suspension {
+                    suspension = current;
+                    if game_tx.send(Message::Suspend(suspension)).is_err() {
+                        return false;
+                    }
                 }
             }
-            index += 1;
-            let mut next = start + index as u32 * interval;
-            while Instant::now() > next {
-                log::warn!("Missed a screenshot");
-                index += 1;
-                next = start + index as u32 * interval;
+            match (event, &triggers) {
+                (GameEvent::Journal(event), Some(triggers)) if event.timestamp > started => {
+                    match triggers.trigger(&event.content) {
+                        Some(trigger) => game_tx.send(Message::Triggered(trigger)).is_ok(),
+                        None => true,
+                    }
+                }
+                _ => true,
             }
-            let _ = status_tx.send(Status::Waiting(next));
-            thread::sleep(next - Instant::now());
         });
+
+        let status = match schedule {
+            Schedule::Interval(_) => Status::Capturing,
+            Schedule::Events(_) => Status::WaitingForEvent,
+        };
+        let settings = Settings::new(Some(schedule.clone()), rules.clone(), high_res, &store);
+        let session = Arc::new(Session::new(&store, settings)?);
+        info!("Starting the session {}", session.id());
+        let session_folder = session.folder().to_owned();
+        let pipeline = Pipeline::new(session.clone(), store.clone());
+        let backlog = pipeline.backlog();
+        let start = Instant::now();
+        let capture = Capture {
+            screenshot,
+            high_res,
+            store,
+            control_rx,
+            status_tx,
+            start,
+            index: 0,
+            next: match schedule {
+                Schedule::Interval(_) => Some(start),
+                Schedule::Events(_) => None,
+            },
+            schedule,
+            rules,
+            session,
+            pipeline,
+            trigger: "interval",
+            paused: false,
+            suspension: None,
+            minimized: false,
+            paused_since: None,
+            low_space: false,
+        };
+        let thread = thread::spawn(move || capture.run());
         Ok(Self {
-            exit_tx,
+            control_tx,
             status_rx,
-            status: Status::Capturing,
+            status,
+            paused: false,
+            session_folder,
+            backlog,
+            thread: Some(thread),
+            stopping: false,
         })
     }
 
+    /// Suspend the captures, keeping the schedule for when it resumes.
+    pub fn pause(&mut self) {
+        if self.control_tx.send(Message::Pause).is_ok() {
+            self.paused = true;
+        }
+    }
+
+    pub fn resume(&mut self) {
+        if self.control_tx.send(Message::Resume).is_ok() {
+            self.paused = false;
+        }
+    }
+
+    /// Where the frames of the session are stored.
+    pub fn session_folder(&self) -> &Path {
+        &self.session_folder
+    }
+
+    /// The screenshots waiting to be stored.
+    pub fn conversion(&self) -> ConversionStatus {
+        self.backlog.status()
+    }
+
+    /// Whether the timelapse was paused by the user, or for lack of disk
+    /// space.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Whether the timelapse stopped by itself.
+    pub fn is_stopped(&self) -> bool {
+        matches!(self.status, Status::Stopped(_))
+    }
+
     pub fn update_status(&mut self) {
         if let Some(status) = self.status_rx.try_iter().last() {
+            if let Status::OutOfSpace(_) = status {
+                self.paused = true;
+            }
             self.status = status;
         }
     }
 
-    pub fn stop(&self) {
-        if let Err(e) = self.exit_tx.send(Exit) {
-            log::error!("Failed to send exit signal to timelapse: {}", e);
+    /// Ask the timelapse to stop, without waiting: the capture in progress
+    /// and the conversion of the screenshots finish in its thread.
+    pub fn request_stop(&mut self) {
+        if self.stopping || self.thread.is_none() {
+            return;
+        }
+        self.stopping = true;
+        // The thread may already be gone if it stopped by itself
+        if let Err(e) = self.control_tx.send(Message::Exit) {
+            if !self.is_stopped() {
+                log::error!("Failed to send exit signal to timelapse: {}", e);
+            }
+        }
+    }
+
+    /// Whether the timelapse thread is over, with all its frames stored.
+    pub fn is_finished(&self) -> bool {
+        self.thread.as_ref().map_or(true, JoinHandle::is_finished)
+    }
+
+    /// Stop the timelapse, waiting for the capture in progress and the
+    /// conversion of the screenshots.
+    pub fn stop(&mut self) {
+        self.request_stop();
+        let Some(thread) = self.thread.take() else {
+            return;
+        };
+        if thread.join().is_err() {
+            log::error!("The timelapse thread panicked");
         }
     }
 }
 
-pub fn take_screenshot(
-    watcher: &mut Watcher,
+impl Drop for TimelapseControl {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// The capture loop, running in its own thread.
+struct Capture {
+    screenshot: Watcher,
+    schedule: Schedule,
+    rules: CaptureRules,
+    session: Arc<Session>,
+    pipeline: Pipeline,
+    /// What triggered the next screenshot.
+    trigger: &'static str,
     high_res: bool,
-    organize: bool,
-    remove_original: bool,
-    folder: &Path,
-) -> Result<PathBuf> {
-    watcher.take_screenshot(high_res).and_then(|s| {
-        if organize {
-            store_screenshot(s, remove_original, folder)
+    store: StoreOptions,
+    control_rx: Receiver<Message>,
+    status_tx: Sender<Status>,
+    /// When the first screenshot of the interval schedule was taken.
+    start: Instant,
+    index: u32,
+    /// When the next screenshot is due, if any.
+    next: Option<Instant>,
+    /// Paused by the user.
+    paused: bool,
+    suspension: Option<Suspension>,
+    /// The game window was minimized at the last capture, and still is.
+    minimized: bool,
+    /// Since when the captures are paused or suspended.
+    paused_since: Option<Instant>,
+    /// Whether the low disk space was already warned about.
+    low_space: bool,
+}
+
+impl Capture {
+    fn run(mut self) {
+        loop {
+            let wait = if self.minimized {
+                Some(Instant::now() + WINDOW_POLL_INTERVAL)
+            } else if self.paused_since.is_some() {
+                None
+            } else {
+                self.next
+            };
+            let message = match wait {
+                Some(next) => self
+                    .control_rx
+                    .recv_timeout(next.saturating_duration_since(Instant::now())),
+                None => self
+                    .control_rx
+                    .recv()
+                    .map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match message {
+                Err(RecvTimeoutError::Timeout) if self.minimized => {
+                    if !self.screenshot.game_minimized() {
+                        info!("The game window is restored, the timelapse can go on");
+                        self.minimized = false;
+                        self.update_pause();
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    if self.capture().is_break() {
+                        self.finish();
+                        return;
+                    }
+                }
+                Ok(Message::Exit) | Err(RecvTimeoutError::Disconnected) => {
+                    info!("Stopping the timelapse");
+                    self.finish();
+                    return;
+                }
+                Ok(Message::Pause) => {
+                    info!("Pausing the timelapse");
+                    self.paused = true;
+                    self.update_pause();
+                }
+                Ok(Message::Resume) => {
+                    info!("Resuming the timelapse");
+                    self.paused = false;
+                    self.update_pause();
+                }
+                Ok(Message::Suspend(suspension)) => {
+                    match suspension {
+                        Some(suspension) => info!("Suspending the timelapse, {}", suspension),
+                        None => info!("The game is back, the timelapse can go on"),
+                    }
+                    self.suspension = suspension;
+                    self.update_pause();
+                }
+                Ok(Message::Triggered(trigger)) => self.triggered(trigger),
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        self.pipeline.finish();
+        self.session.stop();
+    }
+
+    /// Take the due screenshot, unless a disk is too full.
+    fn capture(&mut self) -> ControlFlow<()> {
+        match self.low_space() {
+            Some(low) if low.action == SpaceAction::Stop => {
+                log::error!("Stopping the timelapse, {}", low);
+                let _ = self.status_tx.send(Status::Stopped(low));
+                return ControlFlow::Break(());
+            }
+            Some(low) if low.action == SpaceAction::Pause => {
+                log::warn!("Pausing the timelapse, {}", low);
+                self.paused = true;
+                self.update_pause();
+                let _ = self.status_tx.send(Status::OutOfSpace(low));
+                return ControlFlow::Continue(());
+            }
+            Some(low) => {
+                if !self.low_space {
+                    log::warn!("Running out of disk space, {}", low);
+                }
+                self.low_space = true;
+            }
+            None => self.low_space = false,
+        }
+        if self.rules.auto_pause && self.screenshot.game_minimized() {
+            info!("Suspending the timelapse, {}", Suspension::Minimized);
+            self.minimized = true;
+            self.update_pause();
+            return ControlFlow::Continue(());
+        }
+        if let Some(skip) = self.rules.skip(&self.screenshot.game_state()) {
+            log::info!("Skipped: {}", skip);
+            self.session.record_gap(Gap {
+                time: Local::now(),
+                reason: GapReason::Skipped {
+                    why: skip.to_string(),
+                },
+            });
         } else {
-            Ok(s.path)
+            let _ = self.status_tx.send(Status::Capturing);
+            match Job::capture(
+                &mut self.screenshot,
+                &self.session,
+                self.trigger,
+                self.high_res,
+                &self.store,
+            ) {
+                Ok(job) => {
+                    log::info!("Screenshot taken: {}", job.screenshot.path.display());
+                    self.pipeline.submit(job);
+                }
+                Err(e) => {
+                    log::error!("Failed to take screenshot: {}", e);
+                }
+            }
+        }
+        self.next = match &self.schedule {
+            Schedule::Interval(interval) => {
+                self.index += 1;
+                let mut next = self.start + self.index * *interval;
+                while Instant::now() > next {
+                    log::warn!("Missed a screenshot");
+                    let late = chrono::Duration::from_std(next.elapsed()).unwrap_or_default();
+                    self.session.record_gap(Gap {
+                        time: Local::now() - late,
+                        reason: GapReason::Missed,
+                    });
+                    self.index += 1;
+                    next = self.start + self.index * *interval;
+                }
+                Some(next)
+            }
+            Schedule::Events(_) => None,
+        };
+        self.send_status();
+        ControlFlow::Continue(())
+    }
+
+    /// The disks of the game screenshots and of the frames.
+    fn low_space(&self) -> Option<LowSpace> {
+        let mut folders = vec![self.screenshot.screenshot_dir()];
+        if self.store.organize {
+            folders.push(&self.store.folder);
         }
-    })
+        self.store.space.check(folders)
+    }
+
+    fn triggered(&mut self, trigger: &'static str) {
+        let Schedule::Events(triggers) = &self.schedule else {
+            return;
+        };
+        if self.paused_since.is_some() {
+            return;
+        }
+        let at = Instant::now() + triggers.delay;
+        // Several events in a row make a single screenshot, named after the first one
+        if self.next.is_none() {
+            self.trigger = trigger;
+        }
+        self.next = Some(self.next.map_or(at, |next| next.min(at)));
+        self.send_status();
+    }
+
+    fn update_pause(&mut self) {
+        let paused = self.paused || self.minimized || self.suspension.is_some();
+        match self.paused_since {
+            None if paused => self.paused_since = Some(Instant::now()),
+            Some(since) if !paused => {
+                // Carry on with the same schedule, as if the pause never happened
+                let paused = since.elapsed();
+                self.start += paused;
+                self.next = self.next.map(|next| next + paused);
+                self.paused_since = None;
+            }
+            _ => {}
+        }
+        self.send_status();
+    }
+
+    fn send_status(&self) {
+        let status = if self.paused {
+            Status::Paused
+        } else if self.minimized {
+            Status::Suspended(Suspension::Minimized)
+        } else if let Some(suspension) = self.suspension {
+            Status::Suspended(suspension)
+        } else if let Some(next) = self.next {
+            Status::Waiting(next)
+        } else {
+            Status::WaitingForEvent
+        };
+        let _ = self.status_tx.send(status);
+    }
 }
 
-fn store_screenshot(
-    screenshot: Screenshot,
-    remove_original: bool,
-    folder: &Path,
+/// Take a screenshot and store it right away.
+pub fn take_screenshot(
+    watcher: &mut Watcher,
+    session: &Session,
+    trigger: &str,
+    high_res: bool,
+    store: &StoreOptions,
 ) -> Result<PathBuf> {
-    let now = chrono::Local::now();
-    let folder = folder.join(format!(
-        "{} {}",
-        now.format("%Y-%m-%d"),
-        screenshot.location,
-    ));
+    Job::capture(watcher, session, trigger, high_res, store)?.store(session, store)
+}
+
+/// Convert the screenshot into the session folder.
+pub fn store_screenshot(job: &mut Job, session: &Session, store: &StoreOptions) -> Result<()> {
+    let screenshot = &job.screenshot;
+    if let Some(low) = store.space.check([store.folder.as_path()]) {
+        if low.action >= SpaceAction::Pause {
+            bail!("Not enough space to store the frame, {}", low);
+        }
+    }
+    wait_until_complete(&screenshot.path, WRITE_TIMEOUT)?;
     let image = image::io::Reader::open(&screenshot.path)?.decode()?;
-    std::fs::create_dir_all(&folder)?;
-    let filename = now.format("%H-%M-%S.jpg").to_string();
-    let destination = folder.join(filename);
-    image.save(&destination)?;
+    let image = store.transform.resize(image);
+    let first = session.frame_size().unwrap_or(image.dimensions());
+    let image = store.transform.fit(image, first)?;
+    if let Some(folder) = job.destination.parent() {
+        std::fs::create_dir_all(folder)?;
+    }
+    let mut encoded = vec![];
+    store
+        .format
+        .encode(&image, &mut encoded)
+        .with_context(|| format!("Failed to encode the frame as {}", store.format))?;
+    if store.metadata {
+        let metadata = Metadata {
+            time: job.requested,
+            session: session.id(),
+            sequence: job.sequence,
+            trigger: &job.trigger,
+            context: &screenshot.context,
+        };
+        encoded = metadata.embed(store.format, encoded)?;
+    }
+    write_frame(&mut job.destination, session, &encoded)?;
+    if store.sidecar {
+        let sidecar = Sidecar {
+            version: SIDECAR_VERSION,
+            session: session.id().to_string(),
+            sequence: job.sequence,
+            trigger: job.trigger.clone(),
+            stored: Local::now(),
+            logged: screenshot.timestamp,
+            event: screenshot.event.clone(),
+            context: screenshot.context.clone(),
+        };
+        let write_sidecar = || -> Result<()> {
+            write_atomically(
+                &Sidecar::path(&job.destination),
+                serde_json::to_string_pretty(&sidecar)?.as_bytes(),
+            )
+        };
+        // The frame is stored already
+        if let Err(e) = write_sidecar() {
+            log::error!("Failed to write the sidecar: {:#}", e);
+        }
+    }
+    let original = std::fs::metadata(&screenshot.path)?.len();
+    let stored = encoded.len() as u64;
+    info!(
+        "Stored as {}, {} kB to {} kB, compression ratio {:.1}",
+        store.format,
+        original / 1000,
+        stored / 1000,
+        original as f64 / stored.max(1) as f64
+    );
 
-    if remove_original {
-        info!("Removing original screenshot: {:?}", screenshot.path);
-        std::fs::remove_file(screenshot.path)?;
+    if store.remove_original {
+        verify(&job.destination, &encoded, store.format)?;
+        match &store.trash {
+            Some(trash) => {
+                let moved = move_to_trash(&screenshot.path, &trash.join(session.id()))?;
+                info!("Moved original screenshot to {}", moved.display());
+            }
+            None => {
+                info!("Removing original screenshot: {:?}", screenshot.path);
+                std::fs::remove_file(&screenshot.path)?;
+            }
+        }
     }
 
-    Ok(destination)
+    Ok(())
+}
+
+/// Write the frame at its reserved path, or next to it when another file
+/// appeared there meanwhile.
+fn write_frame(destination: &mut PathBuf, session: &Session, encoded: &[u8]) -> Result<()> {
+    let reserved = destination.clone();
+    loop {
+        match write_atomically(destination, encoded) {
+            Err(e) if is_already_exists(&e) => {
+                log::warn!("{:#}, storing the frame next to it", e);
+                *destination = session.next_copy(&reserved);
+            }
+            result => return result,
+        }
+    }
+}
+
+fn is_already_exists(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == ErrorKind::AlreadyExists)
+}
+
+/// Write a file through a temporary one, so that it is never left
+/// truncated, and never replacing an existing file.
+pub(crate) fn write_atomically(path: &Path, contents: &[u8]) -> Result<()> {
+    write_through_temporary(path, contents, move_new)
+}
+
+/// Write a file through a temporary one, so that it is never left
+/// truncated, replacing its previous version.
+pub(crate) fn replace_atomically(path: &Path, contents: &[u8]) -> Result<()> {
+    write_through_temporary(path, contents, |from, to| std::fs::rename(from, to))
+}
+
+fn write_through_temporary(
+    path: &Path,
+    contents: &[u8],
+    rename: impl FnOnce(&Path, &Path) -> std::io::Result<()>,
+) -> Result<()> {
+    let temporary = temporary_path(path);
+    let write = || -> Result<()> {
+        let mut file = File::create(&temporary)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        rename(&temporary, path)?;
+        Ok(())
+    };
+    write()
+        .inspect_err(|_| {
+            let _ = std::fs::remove_file(&temporary);
+        })
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// The hidden file that a file is written to before it is moved to its path.
+fn temporary_path(path: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+/// Check that the stored frame reads back, before removing the original.
+fn verify(path: &Path, encoded: &[u8], format: OutputFormat) -> Result<()> {
+    let written = std::fs::read(path)?;
+    ensure!(
+        !written.is_empty() && written == encoded,
+        "The frame {} doesn't read back as written",
+        path.display()
+    );
+    // AVIF can't be decoded
+    if !matches!(format, OutputFormat::Avif { .. }) {
+        image::load_from_memory(&written)
+            .with_context(|| format!("The frame {} can't be decoded", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Move a file to the trash folder, without replacing another one.
+fn move_to_trash(path: &Path, trash: &Path) -> Result<PathBuf> {
+    std::fs::create_dir_all(trash)?;
+    let destination = trash.join(path.file_name().unwrap_or_default());
+    for copy in 1.. {
+        let destination = match copy {
+            1 => destination.clone(),
+            copy => with_copy_number(&destination, copy),
+        };
+        match move_new(path, &destination) {
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+            result => return Ok(result.map(|()| destination)?),
+        }
+    }
+    unreachable!("Ran out of file names")
+}
+
+/// Move a file, failing with [`ErrorKind::AlreadyExists`] instead of
+/// replacing another one.
+///
+/// To another drive, the file is copied next to its destination first, so
+/// that it is never found truncated under its new name.
+fn move_new(from: &Path, to: &Path) -> std::io::Result<()> {
+    match rename_new(from, to) {
+        Err(e) if CROSS_DEVICE.is_some() && e.raw_os_error() == CROSS_DEVICE => {
+            let temporary = temporary_path(to);
+            let copy = || -> std::io::Result<()> {
+                std::fs::copy(from, &temporary)?;
+                File::options().write(true).open(&temporary)?.sync_all()?;
+                rename_new(&temporary, to)
+            };
+            if let Err(e) = copy() {
+                let _ = std::fs::remove_file(&temporary);
+                return Err(e);
+            }
+            std::fs::remove_file(from)
+        }
+        result => result,
+    }
+}
+
+/// The OS error of a rename to another drive.
+#[cfg(unix)]
+const CROSS_DEVICE: Option<i32> = Some(libc::EXDEV);
+/// `ERROR_NOT_SAME_DEVICE`
+#[cfg(windows)]
+const CROSS_DEVICE: Option<i32> = Some(17);
+#[cfg(not(any(unix, windows)))]
+const CROSS_DEVICE: Option<i32> = None;
+
+/// Rename a file on the same drive, without replacing another one.
+#[cfg(target_os = "linux")]
+fn rename_new(from: &Path, to: &Path) -> std::io::Result<()> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let from_c = CString::new(from.as_os_str().as_bytes())?;
+    let to_c = CString::new(to.as_os_str().as_bytes())?;
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_renameat2,
+            libc::AT_FDCWD,
+            from_c.as_ptr(),
+            libc::AT_FDCWD,
+            to_c.as_ptr(),
+            libc::RENAME_NOREPLACE,
+        )
+    };
+    if result == 0 {
+        return Ok(());
+    }
+    let error = std::io::Error::last_os_error();
+    match error.raw_os_error() {
+        // Not supported by the kernel or the file system
+        Some(libc::EINVAL | libc::ENOSYS) => rename_unless_exists(from, to),
+        _ => Err(error),
+    }
+}
+
+/// Rename a file on the same drive, without replacing another one.
+#[cfg(windows)]
+fn rename_new(from: &Path, to: &Path) -> std::io::Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows::{
+        core::PCWSTR,
+        Win32::Storage::FileSystem::{MoveFileExW, MOVEFILE_WRITE_THROUGH},
+    };
+
+    let wide =
+        |path: &Path| -> Vec<u16> { path.as_os_str().encode_wide().chain(Some(0)).collect() };
+    let (from, to) = (wide(from), wide(to));
+    // Neither MOVEFILE_REPLACE_EXISTING nor MOVEFILE_COPY_ALLOWED
+    unsafe {
+        MoveFileExW(
+            PCWSTR(from.as_ptr()),
+            PCWSTR(to.as_ptr()),
+            MOVEFILE_WRITE_THROUGH,
+        )
+    }
+    // The Win32 error code, in the low bits of the HRESULT
+    .map_err(|e| std::io::Error::from_raw_os_error(e.code().0 & 0xFFFF))
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn rename_new(from: &Path, to: &Path) -> std::io::Result<()> {
+    rename_unless_exists(from, to)
+}
+
+/// Rename a file unless another one has its new name, for the file systems
+/// that can't check it atomically.
+///
+/// A hard link fails on an existing file, else a rename follows a check.
+#[cfg(not(windows))]
+fn rename_unless_exists(from: &Path, to: &Path) -> std::io::Result<()> {
+    match std::fs::hard_link(from, to) {
+        Ok(()) => std::fs::remove_file(from),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => Err(e),
+        Err(e) if CROSS_DEVICE.is_some() && e.raw_os_error() == CROSS_DEVICE => Err(e),
+        Err(_) if to.symlink_metadata().is_ok() => Err(std::io::Error::new(
+            ErrorKind::AlreadyExists,
+            format!("{} already exists", to.display()),
+        )),
+        Err(_) => std::fs::rename(from, to),
+    }
 }
@@ -0,0 +1,107 @@
+use std::{fmt::Display, io::Write};
+
+use anyhow::{anyhow, Result};
+use image::{
+    codecs::{avif::AvifEncoder, jpeg::JpegEncoder, png::PngEncoder, webp::WebPEncoder},
+    DynamicImage,
+};
+
+/// AVIF is slow to encode, favor the speed over the size.
+const AVIF_SPEED: u8 = 8;
+
+/// The format of the stored frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "format", rename_all = "snake_case")]
+pub enum OutputFormat {
+    Png,
+    /// With a quality from 1 to 100.
+    Jpeg {
+        quality: u8,
+    },
+    /// Lossless, or lossy with a quality from 1 to 100.
+    WebP {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        quality: Option<u8>,
+    },
+    /// With a quality from 1 to 100.
+    Avif {
+        quality: u8,
+    },
+}
+
+impl Default for OutputFormat {
+    fn default() -> Self {
+        Self::Jpeg { quality: 90 }
+    }
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg { .. } => "jpg",
+            OutputFormat::WebP { .. } => "webp",
+            OutputFormat::Avif { .. } => "avif",
+        }
+    }
+
+    /// About how many bytes a pixel of a game screenshot takes, to estimate
+    /// the size of the frames.
+    pub fn bytes_per_pixel(&self) -> f64 {
+        match *self {
+            OutputFormat::Png => 1.5,
+            OutputFormat::Jpeg { quality } => 0.05 + 0.3 * (f64::from(quality) / 100.).powi(2),
+            OutputFormat::WebP { quality: None } => 1.2,
+            OutputFormat::WebP {
+                quality: Some(quality),
+            } => 0.03 + 0.2 * (f64::from(quality) / 100.).powi(2),
+            OutputFormat::Avif { quality } => 0.02 + 0.15 * (f64::from(quality) / 100.).powi(2),
+        }
+    }
+
+    pub fn encode(&self, image: &DynamicImage, writer: impl Write) -> Result<()> {
+        match *self {
+            OutputFormat::Png => image.write_with_encoder(PngEncoder::new(writer))?,
+            OutputFormat::Jpeg { quality } => {
+                // JPEG has no alpha channel
+                let image = DynamicImage::ImageRgb8(image.to_rgb8());
+                image.write_with_encoder(JpegEncoder::new_with_quality(
+                    writer,
+                    quality.clamp(1, 100),
+                ))?
+            }
+            OutputFormat::WebP { quality: None } => {
+                image.write_with_encoder(WebPEncoder::new_lossless(writer))?
+            }
+            OutputFormat::WebP {
+                quality: Some(quality),
+            } => {
+                // The image encoder is lossless only, use libwebp
+                let image = image.to_rgb8();
+                let webp = webp::Encoder::from_rgb(&image, image.width(), image.height())
+                    .encode_simple(false, f32::from(quality.clamp(1, 100)))
+                    .map_err(|e| anyhow!("Failed to encode the WebP frame: {:?}", e))?;
+                let mut writer = writer;
+                writer.write_all(&webp)?;
+            }
+            OutputFormat::Avif { quality } => image.write_with_encoder(
+                AvifEncoder::new_with_speed_quality(writer, AVIF_SPEED, quality.clamp(1, 100)),
+            )?,
+        }
+        Ok(())
+    }
+}
+
+impl Display for OutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputFormat::Png => f.write_str("PNG"),
+            OutputFormat::Jpeg { quality } => write!(f, "JPEG ({}%)", quality),
+            OutputFormat::WebP { quality: None } => f.write_str("WebP (lossless)"),
+            OutputFormat::WebP {
+                quality: Some(quality),
+            } => write!(f, "WebP ({}%)", quality),
+            OutputFormat::Avif { quality } => write!(f, "AVIF ({}%)", quality),
+        }
+    }
+}
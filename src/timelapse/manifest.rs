@@ -0,0 +1,187 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local, Utc};
+
+use crate::screenshot::CaptureContext;
+
+use super::{CaptureRules, FrameTransform, OutputFormat, Schedule, StoreOptions};
+
+/// The file name of the manifest, in the session folder.
+///
+/// It is JSON Lines: one [`Record`] per line, appended as the session goes.
+pub const MANIFEST_FILE: &str = "session.jsonl";
+
+/// The file name of the whole [`Manifest`] as a single JSON document, in the
+/// session folder, for the tools that can't read JSON Lines.
+///
+/// It is replaced after each record.
+pub const MANIFEST_SNAPSHOT_FILE: &str = "session.json";
+
+/// The record of a session, to assemble the video and find its gaps.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Manifest {
+    pub session: String,
+    pub started: DateTime<Local>,
+    pub stopped: Option<DateTime<Local>>,
+    pub settings: Settings,
+    pub frames: Vec<Frame>,
+    /// The captures that didn't make a frame.
+    pub gaps: Vec<Gap>,
+}
+
+/// A line of the manifest file.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Record {
+    /// The first line.
+    Started {
+        session: String,
+        started: DateTime<Local>,
+        settings: Settings,
+    },
+    Frame(Frame),
+    Gap(Gap),
+    Stopped {
+        stopped: DateTime<Local>,
+    },
+}
+
+impl Manifest {
+    /// Read a manifest file.
+    ///
+    /// A last line left incomplete, by a crash for example, is ignored.
+    pub fn read(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let lines: Vec<_> = content.lines().filter(|l| !l.trim().is_empty()).collect();
+        let mut records = vec![];
+        for (i, line) in lines.iter().enumerate() {
+            match serde_json::from_str(line) {
+                Ok(record) => records.push(record),
+                Err(e) if i + 1 == lines.len() => {
+                    log::warn!("Ignoring the incomplete end of {}: {}", path.display(), e);
+                }
+                Err(e) => {
+                    bail!("Invalid line {} of {}: {}", i + 1, path.display(), e)
+                }
+            }
+        }
+        Self::from_records(records).with_context(|| format!("Invalid manifest {}", path.display()))
+    }
+
+    /// Rebuild a manifest from its records, with the frames in sequence order.
+    pub fn from_records(records: impl IntoIterator<Item = Record>) -> Result<Self> {
+        let mut records = records.into_iter();
+        let Some(Record::Started {
+            session,
+            started,
+            settings,
+        }) = records.next()
+        else {
+            bail!("The manifest doesn't start with the session");
+        };
+        let mut manifest = Self {
+            session,
+            started,
+            stopped: None,
+            settings,
+            frames: vec![],
+            gaps: vec![],
+        };
+        for record in records {
+            match record {
+                Record::Started { .. } => bail!("The session is started twice"),
+                Record::Frame(frame) => manifest.frames.push(frame),
+                Record::Gap(gap) => manifest.gaps.push(gap),
+                Record::Stopped { stopped } => manifest.stopped = Some(stopped),
+            }
+        }
+        // The frames are stored out of order by the workers
+        manifest.frames.sort_by_key(|frame| frame.sequence);
+        Ok(manifest)
+    }
+}
+
+/// How the frames of the session were captured.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Settings {
+    /// `None` for the screenshots taken manually.
+    pub schedule: Option<Schedule>,
+    pub rules: CaptureRules,
+    pub high_res: bool,
+    pub format: OutputFormat,
+    pub transform: FrameTransform,
+    pub session_template: String,
+    pub template: String,
+}
+
+impl Settings {
+    pub fn new(
+        schedule: Option<Schedule>,
+        rules: CaptureRules,
+        high_res: bool,
+        store: &StoreOptions,
+    ) -> Self {
+        Self {
+            schedule,
+            rules,
+            high_res,
+            format: store.format,
+            transform: store.transform.clone(),
+            session_template: store.session_template.as_str().to_string(),
+            template: store.template.as_str().to_string(),
+        }
+    }
+}
+
+/// A stored frame.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Frame {
+    pub sequence: u32,
+    /// Relative to the session folder, or where the game wrote it when the
+    /// screenshots are not organized.
+    pub file: PathBuf,
+    /// The file name given by the game.
+    pub original: String,
+    /// When the capture was requested.
+    pub requested: DateTime<Local>,
+    /// When the screenshot was logged in the journal.
+    pub logged: DateTime<Utc>,
+    /// From the request to the screenshot found in the journal, before its
+    /// conversion.
+    pub latency_ms: u64,
+    pub trigger: String,
+    pub location: String,
+    pub context: CaptureContext,
+}
+
+/// A capture that didn't make a frame.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Gap {
+    /// When the capture was due.
+    pub time: DateTime<Local>,
+    #[serde(flatten)]
+    pub reason: GapReason,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum GapReason {
+    /// The previous capture took longer than the interval.
+    Missed,
+    /// Not captured because of the capture rules.
+    Skipped {
+        why: String,
+    },
+    Failed {
+        error: String,
+    },
+    /// Taken, but left where the game wrote it as the conversion was too far
+    /// behind, so its sequence number is unused.
+    Dropped {
+        sequence: u32,
+        /// The file name of the screenshot.
+        original: String,
+    },
+}
@@ -0,0 +1,382 @@
+use anyhow::{bail, ensure, Result};
+use chrono::{DateTime, Local, SecondsFormat};
+
+use crate::screenshot::CaptureContext;
+
+use super::OutputFormat;
+
+/// The namespace of the game details in the XMP packet.
+pub const XMP_NAMESPACE: &str = "https://github.com/plule/ed-timelapse/xmp/1.0/";
+
+const SOFTWARE: &str = concat!("ed-timelapse ", env!("CARGO_PKG_VERSION"));
+
+/// The capture details written in the stored frames, as EXIF and XMP.
+pub struct Metadata<'a> {
+    pub time: DateTime<Local>,
+    pub session: &'a str,
+    pub sequence: u32,
+    pub trigger: &'a str,
+    pub context: &'a CaptureContext,
+}
+
+/// An EXIF field value.
+enum Value {
+    Ascii(String),
+    Long(u32),
+}
+
+impl Value {
+    /// None for a text EXIF can't hold.
+    fn ascii(text: String) -> Option<Self> {
+        text.is_ascii().then_some(Self::Ascii(text))
+    }
+}
+
+impl Metadata<'_> {
+    /// Write the metadata in an encoded frame.
+    ///
+    /// AVIF frames are left as they are.
+    pub fn embed(&self, format: OutputFormat, image: Vec<u8>) -> Result<Vec<u8>> {
+        match format {
+            OutputFormat::Jpeg { .. } => embed_jpeg(image, &self.exif(), &self.xmp()),
+            OutputFormat::Png => embed_png(image, &self.exif(), &self.xmp()),
+            OutputFormat::WebP { .. } => embed_webp(image, &self.exif(), &self.xmp()),
+            OutputFormat::Avif { .. } => Ok(image),
+        }
+    }
+
+    /// Where the frame was taken, like `Sol, Earth, Abraham Lincoln`.
+    pub fn description(&self) -> String {
+        let context = self.context;
+        [&context.system, &context.body, &context.station]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// The EXIF fields, as a little endian TIFF structure.
+    pub fn exif(&self) -> Vec<u8> {
+        let time = self.time.format("%Y:%m:%d %H:%M:%S").to_string();
+        // The names that aren't ASCII are only in the XMP packet
+        let mut ifd0 = vec![];
+        if let Some(description) = Value::ascii(self.description()) {
+            ifd0.push((0x010e, description)); // ImageDescription
+        }
+        ifd0.push((0x0131, Value::Ascii(SOFTWARE.to_string()))); // Software
+        ifd0.push((0x0132, Value::Ascii(time.clone()))); // DateTime
+        let artist = (self.context.commander.as_ref())
+            .and_then(|commander| Value::ascii(format!("CMDR {}", commander)));
+        if let Some(artist) = artist {
+            ifd0.push((0x013b, artist)); // Artist
+        }
+        let exif = [
+            (0x9003, Value::Ascii(time)), // DateTimeOriginal
+            (0x9011, Value::Ascii(self.time.format("%:z").to_string())), // OffsetTimeOriginal
+        ];
+        // The Exif IFD follows the first one
+        let exif_offset = 8 + ifd_len(&ifd0) + 12;
+        ifd0.push((0x8769, Value::Long(exif_offset as u32))); // ExifIFDPointer
+
+        let mut tiff = b"II*\0".to_vec();
+        tiff.extend(8u32.to_le_bytes());
+        write_ifd(&mut tiff, &ifd0);
+        write_ifd(&mut tiff, &exif);
+        tiff
+    }
+
+    /// An XMP packet, with the game details in their own namespace.
+    pub fn xmp(&self) -> String {
+        let context = self.context;
+        let fields = [
+            ("Session", Some(self.session.to_string())),
+            ("Sequence", Some(self.sequence.to_string())),
+            ("Trigger", Some(self.trigger.to_string())),
+            ("Commander", context.commander.clone()),
+            ("Ship", context.ship.clone()),
+            ("ShipName", context.ship_name.clone()),
+            ("System", context.system.clone()),
+            (
+                "SystemAddress",
+                context.system_address.map(|a| a.to_string()),
+            ),
+            (
+                "StarPos",
+                context
+                    .star_pos
+                    .map(|[x, y, z]| format!("{}, {}, {}", x, y, z)),
+            ),
+            ("Body", context.body.clone()),
+            ("Station", context.station.clone()),
+        ];
+        let fields: String = fields
+            .into_iter()
+            .filter_map(|(name, value)| {
+                value.map(|value| format!("   <edt:{0}>{1}</edt:{0}>\n", name, escape(&value)))
+            })
+            .collect();
+        let subjects: String = [&context.system, &context.body, &context.station]
+            .into_iter()
+            .flatten()
+            .map(|subject| format!("     <rdf:li>{}</rdf:li>\n", escape(subject)))
+            .collect();
+        format!(
+            r#"<?xpacket begin="{bom}" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:dc="http://purl.org/dc/elements/1.1/"
+    xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+    xmlns:edt="{namespace}">
+   <xmp:CreateDate>{time}</xmp:CreateDate>
+   <xmp:CreatorTool>{software}</xmp:CreatorTool>
+   <dc:description>
+    <rdf:Alt>
+     <rdf:li xml:lang="x-default">{description}</rdf:li>
+    </rdf:Alt>
+   </dc:description>
+   <dc:subject>
+    <rdf:Bag>
+{subjects}    </rdf:Bag>
+   </dc:subject>
+{fields}  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>"#,
+            bom = '\u{feff}',
+            namespace = XMP_NAMESPACE,
+            time = self.time.to_rfc3339_opts(SecondsFormat::Secs, false),
+            software = SOFTWARE,
+            description = escape(&self.description()),
+        )
+    }
+}
+
+/// The size of an IFD, with its values.
+fn ifd_len(entries: &[(u16, Value)]) -> usize {
+    let values: usize = entries
+        .iter()
+        .map(|(_, value)| match value {
+            Value::Ascii(text) if text.len() + 1 > 4 => (text.len() + 2) & !1,
+            _ => 0,
+        })
+        .sum();
+    2 + 12 * entries.len() + 4 + values
+}
+
+/// Write an IFD and its values at the end of the TIFF structure.
+fn write_ifd(tiff: &mut Vec<u8>, entries: &[(u16, Value)]) {
+    let mut values_offset = tiff.len() + 2 + 12 * entries.len() + 4;
+    let mut values = vec![];
+    tiff.extend((entries.len() as u16).to_le_bytes());
+    for (tag, value) in entries {
+        tiff.extend(tag.to_le_bytes());
+        match value {
+            Value::Ascii(text) => {
+                let mut bytes = text.as_bytes().to_vec();
+                bytes.push(0);
+                tiff.extend(2u16.to_le_bytes());
+                tiff.extend((bytes.len() as u32).to_le_bytes());
+                if bytes.len() <= 4 {
+                    bytes.resize(4, 0);
+                    tiff.extend(bytes);
+                } else {
+                    tiff.extend((values_offset as u32).to_le_bytes());
+                    // The values start on a word boundary
+                    if bytes.len() % 2 == 1 {
+                        bytes.push(0);
+                    }
+                    values_offset += bytes.len();
+                    values.extend(bytes);
+                }
+            }
+            Value::Long(value) => {
+                tiff.extend(4u16.to_le_bytes());
+                tiff.extend(1u32.to_le_bytes());
+                tiff.extend(value.to_le_bytes());
+            }
+        }
+    }
+    // No next IFD
+    tiff.extend(0u32.to_le_bytes());
+    tiff.extend(values);
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Add APP1 segments after the JFIF one.
+fn embed_jpeg(image: Vec<u8>, exif: &[u8], xmp: &str) -> Result<Vec<u8>> {
+    ensure!(image.starts_with(&[0xff, 0xd8]), "Not a JPEG image");
+    let mut position = 2;
+    if image[2..].starts_with(&[0xff, 0xe0]) && image.len() > 6 {
+        position += 2 + usize::from(u16::from_be_bytes([image[4], image[5]]));
+    }
+    let mut segments = vec![];
+    for payload in [
+        [b"Exif\0\0".as_slice(), exif].concat(),
+        [b"http://ns.adobe.com/xap/1.0/\0".as_slice(), xmp.as_bytes()].concat(),
+    ] {
+        let Ok(len) = u16::try_from(payload.len() + 2) else {
+            bail!("The metadata doesn't fit in a JPEG segment");
+        };
+        segments.extend([0xff, 0xe1]);
+        segments.extend(len.to_be_bytes());
+        segments.extend(payload);
+    }
+    let mut jpeg = image;
+    jpeg.splice(position..position, segments);
+    Ok(jpeg)
+}
+
+/// Add `eXIf` and `iTXt` chunks after the header.
+fn embed_png(image: Vec<u8>, exif: &[u8], xmp: &str) -> Result<Vec<u8>> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    // The signature and the IHDR chunk
+    const HEADER_LEN: usize = 8 + 4 + 4 + 13 + 4;
+    ensure!(
+        image.starts_with(SIGNATURE) && image.len() > HEADER_LEN,
+        "Not a PNG image"
+    );
+    let mut chunks = vec![];
+    for (kind, data) in [
+        (b"eXIf", exif.to_vec()),
+        (
+            b"iTXt",
+            [b"XML:com.adobe.xmp\0\0\0\0\0".as_slice(), xmp.as_bytes()].concat(),
+        ),
+    ] {
+        chunks.extend((data.len() as u32).to_be_bytes());
+        let start = chunks.len();
+        chunks.extend(kind);
+        chunks.extend(data);
+        let crc = crc32fast::hash(&chunks[start..]);
+        chunks.extend(crc.to_be_bytes());
+    }
+    let mut png = image;
+    png.splice(HEADER_LEN..HEADER_LEN, chunks);
+    Ok(png)
+}
+
+/// Turn a simple WebP into an extended one, with `EXIF` and `XMP ` chunks.
+fn embed_webp(image: Vec<u8>, exif: &[u8], xmp: &str) -> Result<Vec<u8>> {
+    ensure!(
+        image.starts_with(b"RIFF") && image.get(8..12) == Some(b"WEBP".as_slice()),
+        "Not a WebP image"
+    );
+    ensure!(image.len() >= 30, "Truncated WebP image");
+    let (width, height, alpha) = match &image[12..16] {
+        b"VP8L" => {
+            // The VP8L header packs the size and the alpha flag after its
+            // signature
+            let bits = u32::from_le_bytes([image[21], image[22], image[23], image[24]]);
+            (
+                (bits & 0x3fff) + 1,
+                ((bits >> 14) & 0x3fff) + 1,
+                (bits >> 28) & 1 == 1,
+            )
+        }
+        b"VP8 " => {
+            // The VP8 key frame header has the size after its start code,
+            // and no alpha
+            ensure!(&image[23..26] == b"\x9d\x01\x2a", "Invalid VP8 frame");
+            let width = u16::from_le_bytes([image[26], image[27]]) & 0x3fff;
+            let height = u16::from_le_bytes([image[28], image[29]]) & 0x3fff;
+            (u32::from(width), u32::from(height), false)
+        }
+        _ => bail!("Not a simple WebP image"),
+    };
+
+    let mut header = vec![0x08 | 0x04 | if alpha { 0x10 } else { 0 }, 0, 0, 0];
+    header.extend(&(width - 1).to_le_bytes()[..3]);
+    header.extend(&(height - 1).to_le_bytes()[..3]);
+
+    let mut webp = b"RIFF\0\0\0\0WEBP".to_vec();
+    push_riff_chunk(&mut webp, b"VP8X", &header);
+    webp.extend(&image[12..]);
+    push_riff_chunk(&mut webp, b"EXIF", exif);
+    push_riff_chunk(&mut webp, b"XMP ", xmp.as_bytes());
+    let riff_len = (webp.len() - 8) as u32;
+    webp[4..8].copy_from_slice(&riff_len.to_le_bytes());
+    Ok(webp)
+}
+
+fn push_riff_chunk(riff: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    riff.extend(kind);
+    riff.extend((data.len() as u32).to_le_bytes());
+    riff.extend(data);
+    if data.len() % 2 == 1 {
+        riff.push(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use exif::{In, Reader, Tag};
+
+    use super::*;
+
+    fn read_exif(context: &CaptureContext) -> exif::Exif {
+        let metadata = Metadata {
+            time: Local.with_ymd_and_hms(2024, 6, 22, 20, 0, 0).unwrap(),
+            session: "2024-06-22 20-00-00",
+            sequence: 1,
+            trigger: "manual",
+            context,
+        };
+        Reader::new().read_raw(metadata.exif()).unwrap()
+    }
+
+    fn ascii(exif: &exif::Exif, tag: Tag) -> Option<String> {
+        let field = exif.get_field(tag, In::PRIMARY)?;
+        match &field.value {
+            exif::Value::Ascii(values) => Some(String::from_utf8(values[0].clone()).unwrap()),
+            value => panic!("{} is not ASCII: {:?}", tag, value),
+        }
+    }
+
+    #[test]
+    fn exif_reads_back() {
+        let exif = read_exif(&CaptureContext {
+            commander: Some("Jameson".to_string()),
+            system: Some("Sol".to_string()),
+            body: Some("Earth".to_string()),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            ascii(&exif, Tag::ImageDescription).as_deref(),
+            Some("Sol, Earth")
+        );
+        assert_eq!(ascii(&exif, Tag::Software).as_deref(), Some(SOFTWARE));
+        assert_eq!(
+            ascii(&exif, Tag::DateTimeOriginal).as_deref(),
+            Some("2024:06:22 20:00:00")
+        );
+        assert_eq!(ascii(&exif, Tag::Artist).as_deref(), Some("CMDR Jameson"));
+    }
+
+    #[test]
+    fn names_not_in_ascii_are_left_out_of_the_exif() {
+        let exif = read_exif(&CaptureContext {
+            commander: Some("Jämeson".to_string()),
+            system: Some("Sol".to_string()),
+            station: Some("Gagarin's Mêlée".to_string()),
+            ..Default::default()
+        });
+
+        assert_eq!(ascii(&exif, Tag::ImageDescription), None);
+        assert_eq!(ascii(&exif, Tag::Artist), None);
+        assert_eq!(ascii(&exif, Tag::Software).as_deref(), Some(SOFTWARE));
+        assert_eq!(
+            ascii(&exif, Tag::DateTime).as_deref(),
+            Some("2024:06:22 20:00:00")
+        );
+    }
+}
@@ -0,0 +1,287 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        mpsc::{Receiver, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use log::info;
+
+use crate::screenshot::{Screenshot, Watcher};
+
+use super::{store_screenshot, Fields, Frame, Gap, GapReason, Session, StoreOptions};
+
+/// The screenshots waiting for a worker, beyond which they are left where
+/// the game wrote them.
+const QUEUE_LEN: usize = 16;
+
+/// A screenshot waiting to be stored.
+#[derive(Debug)]
+pub struct Job {
+    pub screenshot: Screenshot,
+    /// Where the frame is stored, reserved when the screenshot was taken, or
+    /// next to it if another file took the place meanwhile.
+    pub destination: PathBuf,
+    pub sequence: u32,
+    pub trigger: String,
+    /// When the capture was requested.
+    pub requested: DateTime<Local>,
+    /// From the request to the screenshot found in the journal.
+    latency: Duration,
+}
+
+impl Job {
+    /// Take a screenshot and reserve its place in the session.
+    pub fn capture(
+        watcher: &mut Watcher,
+        session: &Session,
+        trigger: &str,
+        high_res: bool,
+        store: &StoreOptions,
+    ) -> Result<Self> {
+        let requested = Local::now();
+        let started = Instant::now();
+        let screenshot = match watcher.take_screenshot(high_res) {
+            Ok(screenshot) => screenshot,
+            Err(e) => {
+                session.record_gap(failed(requested, &e));
+                return Err(e);
+            }
+        };
+        let latency = started.elapsed();
+        let (destination, sequence) = if store.organize {
+            session.next_file(store, |sequence| Fields {
+                time: requested,
+                sequence,
+                context: &screenshot.context,
+                location: &screenshot.location,
+                trigger,
+                extension: store.format.extension(),
+            })
+        } else {
+            (screenshot.path.clone(), session.next_sequence())
+        };
+        // Set in capture order, as the workers store the frames in any order
+        let size = (
+            screenshot.event.width.into(),
+            screenshot.event.height.into(),
+        );
+        session.reserve_frame_size(store.transform.output_size(size));
+        Ok(Self {
+            screenshot,
+            destination,
+            sequence,
+            trigger: trigger.to_string(),
+            requested,
+            latency,
+        })
+    }
+
+    /// Convert the screenshot when organizing, and record the frame in the
+    /// session.
+    pub fn store(mut self, session: &Session, store: &StoreOptions) -> Result<PathBuf> {
+        if store.organize {
+            if let Err(e) = store_screenshot(&mut self, session, store) {
+                session.record_gap(failed(self.requested, &e));
+                return Err(e);
+            }
+        }
+        let original = self.original();
+        let screenshot = self.screenshot;
+        session.record_frame(Frame {
+            sequence: self.sequence,
+            file: self
+                .destination
+                .strip_prefix(session.folder())
+                .unwrap_or(&self.destination)
+                .to_owned(),
+            original,
+            requested: self.requested,
+            logged: screenshot.timestamp,
+            latency_ms: self.latency.as_millis() as u64,
+            trigger: self.trigger,
+            location: screenshot.location,
+            context: screenshot.context,
+        });
+        Ok(self.destination)
+    }
+
+    /// The file name of the screenshot.
+    fn original(&self) -> String {
+        self.screenshot
+            .path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+fn failed(time: DateTime<Local>, error: &anyhow::Error) -> Gap {
+    Gap {
+        time,
+        reason: GapReason::Failed {
+            error: format!("{:#}", error),
+        },
+    }
+}
+
+/// Stores the screenshots of a timelapse in worker threads, so that the
+/// captures stay on schedule.
+#[derive(Debug)]
+pub struct Pipeline {
+    tx: Option<SyncSender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+    backlog: Backlog,
+    session: Arc<Session>,
+}
+
+/// Follows the conversions of a pipeline.
+#[derive(Debug, Clone, Default)]
+pub struct Backlog {
+    stats: Arc<Stats>,
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+    queued: AtomicUsize,
+    converting: AtomicUsize,
+    stored: AtomicU64,
+    failed: AtomicU64,
+    /// The time spent storing the frames, in milliseconds, without the
+    /// failures.
+    busy: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ConversionStatus {
+    /// The screenshots waiting for a worker.
+    pub queued: usize,
+    pub converting: usize,
+    pub stored: u64,
+    pub failed: u64,
+    /// The mean time to store a frame, from the start of its conversion.
+    pub mean_time: Option<Duration>,
+}
+
+impl Pipeline {
+    /// Start the workers, about one for two processors.
+    pub fn new(session: Arc<Session>, store: StoreOptions) -> Self {
+        let (tx, rx) = std::sync::mpsc::sync_channel(QUEUE_LEN);
+        let rx = Arc::new(Mutex::new(rx));
+        let backlog = Backlog::default();
+        let count = thread::available_parallelism()
+            .map_or(1, |n| n.get() / 2)
+            .clamp(1, 4);
+        let workers = (0..count)
+            .map(|_| {
+                let rx = rx.clone();
+                let session = session.clone();
+                let store = store.clone();
+                let stats = backlog.stats.clone();
+                thread::spawn(move || work(&rx, &session, &store, &stats))
+            })
+            .collect();
+        Self {
+            tx: Some(tx),
+            workers,
+            backlog,
+            session,
+        }
+    }
+
+    /// Queue a screenshot, or leave it where the game wrote it when the
+    /// workers are too far behind.
+    pub fn submit(&self, job: Job) {
+        let Some(tx) = &self.tx else {
+            return;
+        };
+        let stats = &self.backlog.stats;
+        stats.queued.fetch_add(1, Ordering::SeqCst);
+        match tx.try_send(job) {
+            Ok(()) => {}
+            Err(TrySendError::Full(job) | TrySendError::Disconnected(job)) => {
+                stats.queued.fetch_sub(1, Ordering::SeqCst);
+                stats.failed.fetch_add(1, Ordering::SeqCst);
+                log::warn!(
+                    "The conversion is too far behind, the screenshot is left in {}",
+                    job.screenshot.path.display()
+                );
+                self.session.record_gap(Gap {
+                    time: job.requested,
+                    reason: GapReason::Dropped {
+                        sequence: job.sequence,
+                        original: job.original(),
+                    },
+                });
+            }
+        }
+    }
+
+    pub fn backlog(&self) -> Backlog {
+        self.backlog.clone()
+    }
+
+    /// Store the queued screenshots and stop the workers.
+    pub fn finish(&mut self) {
+        self.tx = None;
+        for worker in self.workers.drain(..) {
+            if worker.join().is_err() {
+                log::error!("A conversion worker panicked");
+            }
+        }
+    }
+}
+
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+fn work(rx: &Mutex<Receiver<Job>>, session: &Session, store: &StoreOptions, stats: &Stats) {
+    loop {
+        let job = rx.lock().unwrap().recv();
+        let Ok(job) = job else {
+            return;
+        };
+        stats.queued.fetch_sub(1, Ordering::SeqCst);
+        stats.converting.fetch_add(1, Ordering::SeqCst);
+        let start = Instant::now();
+        match job.store(session, store) {
+            Ok(path) => {
+                info!("Screenshot stored: {}", path.display());
+                let busy = start.elapsed().as_millis() as u64;
+                stats.busy.fetch_add(busy, Ordering::SeqCst);
+                stats.stored.fetch_add(1, Ordering::SeqCst);
+            }
+            Err(e) => {
+                log::error!("Failed to store screenshot: {}", e);
+                stats.failed.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        stats.converting.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Backlog {
+    pub fn status(&self) -> ConversionStatus {
+        let stats = &self.stats;
+        let stored = stats.stored.load(Ordering::SeqCst);
+        let failed = stats.failed.load(Ordering::SeqCst);
+        let busy = stats.busy.load(Ordering::SeqCst);
+        ConversionStatus {
+            queued: stats.queued.load(Ordering::SeqCst),
+            converting: stats.converting.load(Ordering::SeqCst),
+            stored,
+            failed,
+            mean_time: (stored > 0).then(|| Duration::from_millis(busy / stored)),
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use std::fmt::Display;
+
+use crate::screenshot::{FlightState, GameState, GuiFocus};
+
+use super::Suspension;
+
+/// When not to take the screenshots, from the game state.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct CaptureRules {
+    /// Suspend the timelapse in the main menu, the maps and the codex, and
+    /// while the game window is minimized.
+    pub auto_pause: bool,
+    /// Skip the frames of the hyperspace tunnel and the loading screen.
+    pub skip_hyperspace: bool,
+    /// Skip the frames of the main menu, the maps and the codex.
+    pub skip_menus: bool,
+    /// Skip the frames of the station menu when docked.
+    pub skip_station_services: bool,
+}
+
+impl Default for CaptureRules {
+    fn default() -> Self {
+        Self {
+            auto_pause: true,
+            skip_hyperspace: true,
+            skip_menus: true,
+            skip_station_services: true,
+        }
+    }
+}
+
+/// Why a frame is skipped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Skip {
+    Hyperspace,
+    Menu(Suspension),
+    StationServices,
+}
+
+impl CaptureRules {
+    /// Whether the frame should be skipped in this game state.
+    pub fn skip(&self, state: &GameState) -> Option<Skip> {
+        // A cancelled jump is not logged, the status tells it
+        let jumping = state.context.state == FlightState::Hyperspace
+            && state.status.map_or(true, |status| status.fsd_jump);
+        if self.skip_hyperspace && jumping {
+            return Some(Skip::Hyperspace);
+        }
+        if self.skip_menus {
+            if let Some(menu) = Suspension::of(state) {
+                return Some(Skip::Menu(menu));
+            }
+        }
+        let station_services = state
+            .status
+            .is_some_and(|status| status.gui_focus == GuiFocus::StationServices);
+        if self.skip_station_services && station_services {
+            return Some(Skip::StationServices);
+        }
+        None
+    }
+}
+
+impl Display for Skip {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Skip::Hyperspace => f.write_str("in hyperspace"),
+            Skip::Menu(menu) => menu.fmt(f),
+            Skip::StationServices => f.write_str("in the station services"),
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use std::{
+    collections::HashSet,
+    fs::OpenOptions,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+
+use super::{
+    replace_atomically, Fields, Frame, Gap, Manifest, Record, Settings, Sidecar, StoreOptions,
+    MANIFEST_FILE, MANIFEST_SNAPSHOT_FILE,
+};
+
+/// A timelapse, storing its frames in its own folder, in capture order.
+///
+/// It is shared by the capture loop and the conversion workers.
+#[derive(Debug)]
+pub struct Session {
+    /// Names the folder of the session, from its start time.
+    id: String,
+    folder: PathBuf,
+    started: DateTime<Local>,
+    state: Mutex<State>,
+    /// Held while appending to the manifest file, without the state.
+    manifest_file: Mutex<()>,
+}
+
+#[derive(Debug)]
+struct State {
+    manifest: Manifest,
+    /// The sequence number of the next frame.
+    next: u32,
+    /// The size of the first reserved frame.
+    frame_size: Option<(u32, u32)>,
+    /// The paths given to the frames, which may not be written yet.
+    reserved: HashSet<PathBuf>,
+    /// The manifest records not written yet, waiting for the first capture.
+    pending: Vec<Record>,
+}
+
+impl Session {
+    /// Start a session in the timelapse folder, named by the session
+    /// template.
+    ///
+    /// Its folder is claimed right away, so that no other session starting
+    /// meanwhile takes the same name. It is removed on stop if nothing was
+    /// captured.
+    pub fn new(store: &StoreOptions, settings: Settings) -> Result<Self> {
+        let timelapse_folder = &store.folder;
+        std::fs::create_dir_all(timelapse_folder)
+            .with_context(|| format!("Failed to create {}", timelapse_folder.display()))?;
+        let started = Local::now();
+        let base_id = store.session_template.render_session(started);
+        let mut copy = 1;
+        let id = loop {
+            let id = match copy {
+                1 => base_id.clone(),
+                copy => format!("{} ({})", base_id, copy),
+            };
+            let folder = timelapse_folder.join(&id);
+            match std::fs::create_dir(&folder) {
+                Ok(()) => break id,
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => copy += 1,
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to create {}", folder.display()))
+                }
+            }
+        };
+        Ok(Self {
+            folder: timelapse_folder.join(&id),
+            state: Mutex::new(State {
+                manifest: Manifest {
+                    session: id.clone(),
+                    started,
+                    stopped: None,
+                    settings: settings.clone(),
+                    frames: vec![],
+                    gaps: vec![],
+                },
+                next: 1,
+                frame_size: None,
+                reserved: HashSet::new(),
+                pending: vec![Record::Started {
+                    session: id.clone(),
+                    started,
+                    settings: settings.clone(),
+                }],
+            }),
+            manifest_file: Mutex::new(()),
+            id,
+            started,
+        })
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn folder(&self) -> &Path {
+        &self.folder
+    }
+
+    pub fn started(&self) -> DateTime<Local> {
+        self.started
+    }
+
+    pub fn stopped(&self) -> Option<DateTime<Local>> {
+        self.state().manifest.stopped
+    }
+
+    pub fn manifest(&self) -> Manifest {
+        self.state().manifest.clone()
+    }
+
+    pub fn settings(&self) -> Settings {
+        self.state().manifest.settings.clone()
+    }
+
+    pub fn stop(&self) {
+        {
+            let mut state = self.state();
+            if state.manifest.stopped.is_some() {
+                return;
+            }
+            let stopped = Local::now();
+            state.manifest.stopped = Some(stopped);
+            state.pending.push(Record::Stopped { stopped });
+        }
+        self.write_manifest();
+        // Only empty when nothing was captured
+        let _ = std::fs::remove_dir(&self.folder);
+    }
+
+    /// Add a frame to the manifest, in the order of the sequence numbers.
+    pub fn record_frame(&self, frame: Frame) {
+        {
+            let mut state = self.state();
+            state.pending.push(Record::Frame(frame.clone()));
+            let frames = &mut state.manifest.frames;
+            let index = frames.partition_point(|f| f.sequence < frame.sequence);
+            frames.insert(index, frame);
+        }
+        self.write_manifest();
+    }
+
+    pub fn record_gap(&self, gap: Gap) {
+        {
+            let mut state = self.state();
+            state.pending.push(Record::Gap(gap.clone()));
+            state.manifest.gaps.push(gap);
+        }
+        self.write_manifest();
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    /// Append the pending records to the manifest next to the frames, from
+    /// the first capture, even when the frames are left where the game wrote
+    /// them, and replace its snapshot.
+    ///
+    /// The state is only locked to take the records, not while writing.
+    fn write_manifest(&self) {
+        let _file = self.manifest_file.lock().unwrap();
+        let (records, snapshot) = {
+            let mut state = self.state();
+            if state.manifest.frames.is_empty() && state.manifest.gaps.is_empty() {
+                return;
+            }
+            (std::mem::take(&mut state.pending), state.manifest.clone())
+        };
+        if records.is_empty() {
+            return;
+        }
+        let write = || -> Result<()> {
+            let mut lines = String::new();
+            for record in &records {
+                lines += &serde_json::to_string(record)?;
+                lines.push('\n');
+            }
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(self.folder.join(MANIFEST_FILE))?
+                .write_all(lines.as_bytes())?;
+            Ok(())
+        };
+        if let Err(e) = write() {
+            log::error!("Failed to write the session manifest: {}", e);
+            // Retried with the next record
+            self.state().pending.splice(0..0, records);
+        }
+        let write_snapshot = || -> Result<()> {
+            let json = serde_json::to_vec_pretty(&snapshot)?;
+            replace_atomically(&self.folder.join(MANIFEST_SNAPSHOT_FILE), &json)
+        };
+        if let Err(e) = write_snapshot() {
+            log::error!("Failed to write the session manifest snapshot: {:#}", e);
+        }
+    }
+
+    /// The size of the first reserved frame, that the others are fitted to.
+    pub(super) fn frame_size(&self) -> Option<(u32, u32)> {
+        self.state().frame_size
+    }
+
+    /// Set the size of the frames, if this is the first reserved one.
+    pub(super) fn reserve_frame_size(&self, size: (u32, u32)) {
+        self.state().frame_size.get_or_insert(size);
+    }
+
+    /// The sequence number of a frame left where the game wrote it.
+    pub(super) fn next_sequence(&self) -> u32 {
+        let mut state = self.state();
+        state.next += 1;
+        state.next - 1
+    }
+
+    /// Reserve the path and sequence number of the next frame, never an
+    /// existing file.
+    pub(super) fn next_file<'a>(
+        &self,
+        store: &StoreOptions,
+        fields: impl Fn(u32) -> Fields<'a>,
+    ) -> (PathBuf, u32) {
+        let mut state = self.state();
+        let render = |sequence| self.folder.join(store.template.render(&fields(sequence)));
+        let taken = |path: &Path| is_taken(path) || state.reserved.contains(path);
+        let mut sequence = state.next;
+        let mut path = render(sequence);
+        let mut copy = 1;
+        while taken(&path) {
+            if store.template.uses_sequence() {
+                sequence += 1;
+                path = render(sequence);
+            } else {
+                copy += 1;
+                path = with_copy_number(&render(sequence), copy);
+            }
+        }
+        state.next = sequence + 1;
+        state.reserved.insert(path.clone());
+        (path, sequence)
+    }
+
+    /// Reserve another path for a frame, when a file appeared at its
+    /// reserved one.
+    pub(super) fn next_copy(&self, path: &Path) -> PathBuf {
+        let mut state = self.state();
+        let copy = (2..)
+            .map(|copy| with_copy_number(path, copy))
+            .find(|copy| !is_taken(copy) && !state.reserved.contains(copy))
+            .expect("Ran out of file names");
+        state.reserved.insert(copy.clone());
+        copy
+    }
+}
+
+/// Whether a frame, or a sidecar left without it, is at this path.
+fn is_taken(path: &Path) -> bool {
+    path.exists() || Sidecar::path(path).exists()
+}
+
+/// `name.ext` becomes `name (2).ext`.
+pub(super) fn with_copy_number(path: &Path, copy: u32) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{} ({}).{}", stem, copy, extension.to_string_lossy()),
+        None => format!("{} ({})", stem, copy),
+    };
+    path.with_file_name(name)
+}
@@ -0,0 +1,36 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, Utc};
+use ed_journals::logs::content::log_event_content::screenshot_event::ScreenshotEvent;
+
+use crate::screenshot::CaptureContext;
+
+/// The version of the sidecar schema, bumped when a field is changed or
+/// removed. New fields may be added without a new version.
+pub const SIDECAR_VERSION: u32 = 1;
+
+/// The details of a stored frame, written in a JSON file next to it.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Sidecar {
+    pub version: u32,
+    pub session: String,
+    pub sequence: u32,
+    pub trigger: String,
+    /// When the frame was stored.
+    pub stored: DateTime<Local>,
+    /// When the screenshot was logged in the journal.
+    pub logged: DateTime<Utc>,
+    /// The `Screenshot` journal event, with the position on the surface when
+    /// landed.
+    pub event: ScreenshotEvent,
+    pub context: CaptureContext,
+}
+
+impl Sidecar {
+    /// `00001.jpg` has its sidecar in `00001.jpg.json`.
+    pub fn path(frame: &Path) -> PathBuf {
+        let mut name = frame.file_name().unwrap_or_default().to_owned();
+        name.push(".json");
+        frame.with_file_name(name)
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+
+use super::{Resolution, StoreOptions};
+
+/// The size of a screenshot as the game writes it, a 24 bits BMP.
+const BMP_BYTES_PER_PIXEL: f64 = 3.;
+
+/// The free disk space, in megabytes, below which a timelapse reacts.
+///
+/// Checked on the disks of the game screenshots and of the timelapse folder,
+/// before each capture and before storing each frame.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct SpaceGuard {
+    /// Log a warning.
+    pub warn_below: Option<u64>,
+    /// Pause the timelapse, until it is resumed.
+    pub pause_below: Option<u64>,
+    /// Stop the timelapse.
+    pub stop_below: Option<u64>,
+}
+
+impl Default for SpaceGuard {
+    fn default() -> Self {
+        Self {
+            warn_below: Some(5_000),
+            pause_below: Some(1_000),
+            stop_below: None,
+        }
+    }
+}
+
+/// What a timelapse does when a disk is running out of space, from the
+/// least to the most drastic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SpaceAction {
+    Warn,
+    Pause,
+    Stop,
+}
+
+/// A disk with less free space than a threshold of the guard.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LowSpace {
+    pub folder: PathBuf,
+    /// In bytes.
+    pub free: u64,
+    pub action: SpaceAction,
+}
+
+impl SpaceGuard {
+    /// The most drastic action for the disks of these folders, if any.
+    ///
+    /// A disk whose free space can't be read is only logged.
+    pub fn check<'a>(&self, folders: impl IntoIterator<Item = &'a Path>) -> Option<LowSpace> {
+        folders
+            .into_iter()
+            .filter_map(|folder| match free_space(folder) {
+                Ok(free) => self.action(free).map(|action| LowSpace {
+                    folder: folder.to_owned(),
+                    free,
+                    action,
+                }),
+                Err(e) => {
+                    log::warn!(
+                        "Failed to read the free space of {}: {}",
+                        folder.display(),
+                        e
+                    );
+                    None
+                }
+            })
+            .max_by_key(|low| low.action)
+    }
+
+    fn action(&self, free: u64) -> Option<SpaceAction> {
+        let below = |threshold: Option<u64>| {
+            threshold.is_some_and(|mb| free < mb.saturating_mul(1_000_000))
+        };
+        if below(self.stop_below) {
+            Some(SpaceAction::Stop)
+        } else if below(self.pause_below) {
+            Some(SpaceAction::Pause)
+        } else if below(self.warn_below) {
+            Some(SpaceAction::Warn)
+        } else {
+            None
+        }
+    }
+}
+
+impl Display for LowSpace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "only {} MB left on the disk of {}",
+            self.free / 1_000_000,
+            self.folder.display()
+        )
+    }
+}
+
+/// The disk space a frame takes, roughly, from a screenshot of this size.
+///
+/// Counts the originals left where the game wrote them or moved to the trash
+/// folder, which is in the timelapse folder.
+pub fn frame_size_estimate(store: &StoreOptions, screenshot: Resolution) -> u64 {
+    let pixels = |(width, height): (u32, u32)| f64::from(width) * f64::from(height);
+    let original = pixels((screenshot.width, screenshot.height)) * BMP_BYTES_PER_PIXEL;
+    if !store.organize {
+        return original as u64;
+    }
+    let size = store
+        .transform
+        .output_size((screenshot.width, screenshot.height));
+    let mut bytes = pixels(size) * store.format.bytes_per_pixel();
+    if !store.remove_original || store.trash.is_some() {
+        bytes += original;
+    }
+    bytes as u64
+}
+
+/// The space available to the user on the disk of a folder, in bytes.
+///
+/// The folder may not exist yet, then its closest existing parent tells.
+pub fn free_space(folder: &Path) -> Result<u64> {
+    let existing = folder
+        .ancestors()
+        .find(|path| path.exists())
+        .unwrap_or(folder);
+    platform_free_space(existing)
+}
+
+#[cfg(unix)]
+// The field types depend on the platform
+#[allow(clippy::useless_conversion)]
+fn platform_free_space(path: &Path) -> Result<u64> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(u64::from(stat.f_bavail) * u64::from(stat.f_frsize))
+}
+
+#[cfg(windows)]
+fn platform_free_space(path: &Path) -> Result<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows::{core::PCWSTR, Win32::Storage::FileSystem::GetDiskFreeSpaceExW};
+
+    let path: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut free = 0;
+    unsafe {
+        GetDiskFreeSpaceExW(
+            PCWSTR(path.as_ptr()),
+            Some(&mut free as *mut u64),
+            None,
+            None,
+        )?
+    };
+    Ok(free)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn platform_free_space(_path: &Path) -> Result<u64> {
+    anyhow::bail!("Reading the free disk space is not supported on this platform")
+}
@@ -0,0 +1,53 @@
+use std::fmt::Display;
+
+use crate::screenshot::{GameState, GuiFocus};
+
+/// Why the captures are suspended automatically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Suspension {
+    MainMenu,
+    GalaxyMap,
+    SystemMap,
+    Codex,
+    /// The game window is minimized, only known when capturing.
+    Minimized,
+}
+
+impl Suspension {
+    /// Whether the game shows a screen not worth capturing.
+    pub fn of(state: &GameState) -> Option<Self> {
+        let music_track = state.context.music_track.as_deref();
+        if music_track == Some("MainMenu") {
+            return Some(Suspension::MainMenu);
+        }
+        match &state.status {
+            Some(status) if !status.in_game => Some(Suspension::MainMenu),
+            Some(status) => match status.gui_focus {
+                GuiFocus::GalaxyMap => Some(Suspension::GalaxyMap),
+                GuiFocus::SystemMap | GuiFocus::Orrery => Some(Suspension::SystemMap),
+                GuiFocus::Codex => Some(Suspension::Codex),
+                _ => None,
+            },
+            // Without the status file, the music is all there is
+            None => match music_track {
+                Some("GalaxyMap") => Some(Suspension::GalaxyMap),
+                Some("SystemMap") => Some(Suspension::SystemMap),
+                Some("Codex") => Some(Suspension::Codex),
+                _ => None,
+            },
+        }
+    }
+}
+
+impl Display for Suspension {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            Suspension::MainMenu => "in the main menu",
+            Suspension::GalaxyMap => "in the galaxy map",
+            Suspension::SystemMap => "in the system map",
+            Suspension::Codex => "in the codex",
+            Suspension::Minimized => "with the game window minimized",
+        };
+        f.write_str(reason)
+    }
+}
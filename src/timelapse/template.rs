@@ -0,0 +1,251 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local};
+
+use crate::screenshot::CaptureContext;
+
+/// The frames of a session sort in capture order.
+pub const DEFAULT_TEMPLATE: &str = "{seq:05}_{time}.{ext}";
+
+/// The sessions sort by their start time.
+pub const DEFAULT_SESSION_TEMPLATE: &str = "{date} {time}";
+
+/// The placeholders known when a session starts.
+const TIME_PLACEHOLDERS: &[&str] = &[
+    "date", "time", "year", "month", "day", "hour", "minute", "second",
+];
+
+const PLACEHOLDERS: &[&str] = &[
+    "date",
+    "time",
+    "year",
+    "month",
+    "day",
+    "hour",
+    "minute",
+    "second",
+    "seq",
+    "system",
+    "body",
+    "station",
+    "location",
+    "commander",
+    "ship",
+    "trigger",
+    "ext",
+];
+
+/// A pattern naming the stored frames, relative to the session folder, or
+/// naming the session folders in the timelapse folder.
+///
+/// Placeholders are written in braces, like `{system}`, and `/` separates
+/// the folders. The sequence number can be zero-padded with `{seq:05}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NameTemplate {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Text(String),
+    Placeholder { name: String, width: usize },
+    Separator,
+}
+
+/// The values of the placeholders for a frame.
+pub struct Fields<'a> {
+    pub time: DateTime<Local>,
+    pub sequence: u32,
+    pub context: &'a CaptureContext,
+    /// The body or system, as logged with the screenshot.
+    pub location: &'a str,
+    /// What triggered the capture, like `interval` or `FSDJump`.
+    pub trigger: &'a str,
+    pub extension: &'a str,
+}
+
+impl Default for NameTemplate {
+    fn default() -> Self {
+        Self::parse(DEFAULT_TEMPLATE).expect("Invalid default template")
+    }
+}
+
+impl NameTemplate {
+    /// The default template of the session folders.
+    pub fn default_session() -> Self {
+        Self::parse_session(DEFAULT_SESSION_TEMPLATE).expect("Invalid default session template")
+    }
+
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut segments = vec![];
+        let mut text = String::new();
+        let mut chars = source.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => bail!("Unclosed {{ in the template"),
+                        }
+                    }
+                    let (name, spec) = placeholder
+                        .split_once(':')
+                        .unwrap_or((placeholder.as_str(), ""));
+                    if name == "session" {
+                        bail!("The frames are already in their session folder, name it with the session folder template");
+                    }
+                    if !PLACEHOLDERS.contains(&name) {
+                        bail!("Unknown placeholder {{{}}}", name);
+                    }
+                    let width = match spec {
+                        "" => 0,
+                        _ if name == "seq" => spec
+                            .parse()
+                            .with_context(|| format!("Invalid width in {{{}}}", placeholder))?,
+                        _ => bail!("Only {{seq}} can have a width"),
+                    };
+                    segments.push(Segment::Text(std::mem::take(&mut text)));
+                    segments.push(Segment::Placeholder {
+                        name: name.to_string(),
+                        width,
+                    });
+                }
+                '}' => bail!("Unmatched }} in the template"),
+                '/' | '\\' => {
+                    if text == ".." {
+                        bail!("The template can't leave the timelapse folder");
+                    }
+                    segments.push(Segment::Text(std::mem::take(&mut text)));
+                    segments.push(Segment::Separator);
+                }
+                c => text.push(c),
+            }
+        }
+        segments.push(Segment::Text(text));
+        segments.retain(|s| !matches!(s, Segment::Text(t) if t.is_empty()));
+        match segments.first() {
+            None => bail!("The template is empty"),
+            Some(Segment::Separator) => bail!("The template must be relative"),
+            _ => {}
+        }
+        if segments.last() == Some(&Segment::Separator) {
+            bail!("The template must end with a file name");
+        }
+        Ok(Self {
+            source: source.to_string(),
+            segments,
+        })
+    }
+
+    /// Parse a template naming the session folders, from their start time.
+    pub fn parse_session(source: &str) -> Result<Self> {
+        let template = Self::parse(source)?;
+        for segment in &template.segments {
+            match segment {
+                Segment::Separator => bail!("The session folder can't be in a subfolder"),
+                Segment::Placeholder { name, .. }
+                    if !TIME_PLACEHOLDERS.contains(&name.as_str()) =>
+                {
+                    bail!("{{{}}} is not known when the session starts", name)
+                }
+                _ => {}
+            }
+        }
+        Ok(template)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Whether the names contain the sequence number.
+    pub fn uses_sequence(&self) -> bool {
+        self.segments
+            .iter()
+            .any(|s| matches!(s, Segment::Placeholder { name, .. } if name == "seq"))
+    }
+
+    /// The name of a session folder, from the start time of the session.
+    pub fn render_session(&self, started: DateTime<Local>) -> String {
+        let context = CaptureContext::default();
+        let fields = Fields {
+            time: started,
+            sequence: 0,
+            context: &context,
+            location: "",
+            trigger: "",
+            extension: "",
+        };
+        self.render(&fields).to_string_lossy().into_owned()
+    }
+
+    pub fn render(&self, fields: &Fields<'_>) -> PathBuf {
+        let mut path = PathBuf::new();
+        let mut component = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => component.push_str(text),
+                Segment::Placeholder { name, width } => {
+                    component.push_str(&sanitize(&fields.value(name, *width)));
+                }
+                Segment::Separator => path.push(finish_component(&mut component)),
+            }
+        }
+        path.push(finish_component(&mut component));
+        path
+    }
+}
+
+impl Fields<'_> {
+    fn value(&self, name: &str, width: usize) -> String {
+        let unknown = || "Unknown".to_string();
+        match name {
+            "date" => self.time.format("%Y-%m-%d").to_string(),
+            "time" => self.time.format("%H-%M-%S").to_string(),
+            "year" => self.time.format("%Y").to_string(),
+            "month" => self.time.format("%m").to_string(),
+            "day" => self.time.format("%d").to_string(),
+            "hour" => self.time.format("%H").to_string(),
+            "minute" => self.time.format("%M").to_string(),
+            "second" => self.time.format("%S").to_string(),
+            "seq" => format!("{:0width$}", self.sequence, width = width),
+            "system" => self.context.system.clone().unwrap_or_else(unknown),
+            "body" => self.context.body.clone().unwrap_or_else(unknown),
+            "station" => self.context.station.clone().unwrap_or_else(unknown),
+            "location" => self.location.to_string(),
+            "commander" => self.context.commander.clone().unwrap_or_else(unknown),
+            "ship" => self.context.ship.clone().unwrap_or_else(unknown),
+            "trigger" => self.trigger.to_string(),
+            "ext" => self.extension.to_string(),
+            _ => unreachable!("Placeholders are checked when parsing"),
+        }
+    }
+}
+
+/// Replace the characters that are not allowed in file names.
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Windows doesn't allow names ending with a dot or a space.
+fn finish_component(component: &mut String) -> String {
+    let name = std::mem::take(component);
+    let name = name.trim_end_matches(['.', ' ']).trim_start();
+    if name.is_empty() || name == ".." {
+        "_".to_string()
+    } else {
+        name.to_string()
+    }
+}
@@ -0,0 +1,192 @@
+use std::fmt::Display;
+
+use anyhow::{bail, Result};
+use image::{imageops::FilterType, DynamicImage, GenericImageView};
+
+/// How the screenshots are cropped and resized before being stored.
+#[derive(Debug, Clone, PartialEq, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct FrameTransform {
+    /// Crop the center of the frames to this aspect ratio.
+    pub aspect: Option<AspectRatio>,
+    /// Downscale the frames to fit in this size, they are never upscaled.
+    pub max_size: Option<Resolution>,
+    pub filter: ResizeFilter,
+    /// What to do with the frames not the size of the first one of the session.
+    pub mismatch: SizeMismatch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AspectRatio {
+    /// 16:9
+    Widescreen,
+    /// 21:9
+    Ultrawide,
+    /// 1:1
+    Square,
+    /// 9:16, for vertical videos.
+    Vertical,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResizeFilter {
+    Nearest,
+    Triangle,
+    #[default]
+    CatmullRom,
+    Lanczos3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SizeMismatch {
+    /// Store the frame as it is.
+    #[default]
+    Keep,
+    /// Fit the frame in the size of the first one, with black borders.
+    Pad,
+    /// Don't store the frame.
+    Reject,
+}
+
+impl AspectRatio {
+    pub const ALL: [AspectRatio; 4] = [
+        AspectRatio::Widescreen,
+        AspectRatio::Ultrawide,
+        AspectRatio::Square,
+        AspectRatio::Vertical,
+    ];
+
+    /// The width and height of the ratio.
+    pub fn ratio(&self) -> (u32, u32) {
+        match self {
+            AspectRatio::Widescreen => (16, 9),
+            AspectRatio::Ultrawide => (21, 9),
+            AspectRatio::Square => (1, 1),
+            AspectRatio::Vertical => (9, 16),
+        }
+    }
+}
+
+impl Default for Resolution {
+    fn default() -> Self {
+        Self {
+            width: 1920,
+            height: 1080,
+        }
+    }
+}
+
+impl From<ResizeFilter> for FilterType {
+    fn from(filter: ResizeFilter) -> Self {
+        match filter {
+            ResizeFilter::Nearest => FilterType::Nearest,
+            ResizeFilter::Triangle => FilterType::Triangle,
+            ResizeFilter::CatmullRom => FilterType::CatmullRom,
+            ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+impl FrameTransform {
+    /// Crop and downscale a frame.
+    pub fn resize(&self, mut image: DynamicImage) -> DynamicImage {
+        if let Some(aspect) = self.aspect {
+            image = crop_to_aspect(&image, aspect.ratio());
+        }
+        if let Some(max) = self.max_size {
+            if image.width() > max.width || image.height() > max.height {
+                image = image.resize(max.width, max.height, self.filter.into());
+            }
+        }
+        image
+    }
+
+    /// The size of a frame of this size once resized.
+    pub fn output_size(&self, (width, height): (u32, u32)) -> (u32, u32) {
+        let (mut width, mut height) = match self.aspect {
+            Some(aspect) => crop_size((width, height), aspect.ratio()),
+            None => (width, height),
+        };
+        if let Some(max) = self.max_size {
+            if width > max.width || height > max.height {
+                let scale = f64::min(
+                    f64::from(max.width) / f64::from(width),
+                    f64::from(max.height) / f64::from(height),
+                );
+                width = ((f64::from(width) * scale).round() as u32).max(1);
+                height = ((f64::from(height) * scale).round() as u32).max(1);
+            }
+        }
+        (width, height)
+    }
+
+    /// Bring a resized frame to the size of the first frame of the session.
+    pub fn fit(&self, image: DynamicImage, first: (u32, u32)) -> Result<DynamicImage> {
+        let size = image.dimensions();
+        if size == first {
+            return Ok(image);
+        }
+        match self.mismatch {
+            SizeMismatch::Keep => Ok(image),
+            SizeMismatch::Pad => Ok(pad(&image, first, self.filter.into())),
+            SizeMismatch::Reject => bail!(
+                "The frame is {}x{}, unlike the {}x{} frames of the session",
+                size.0,
+                size.1,
+                first.0,
+                first.1
+            ),
+        }
+    }
+}
+
+/// Keep the center of the image.
+fn crop_to_aspect(image: &DynamicImage, ratio: (u32, u32)) -> DynamicImage {
+    let (width, height) = image.dimensions();
+    let (crop_width, crop_height) = crop_size((width, height), ratio);
+    image.crop_imm(
+        (width - crop_width) / 2,
+        (height - crop_height) / 2,
+        crop_width.max(1),
+        crop_height.max(1),
+    )
+}
+
+/// The largest size of this aspect ratio fitting in the image.
+fn crop_size((width, height): (u32, u32), (ratio_width, ratio_height): (u32, u32)) -> (u32, u32) {
+    // Compare width / height to the ratio without rounding
+    if u64::from(width) * u64::from(ratio_height) > u64::from(height) * u64::from(ratio_width) {
+        (height * ratio_width / ratio_height, height)
+    } else {
+        (width, width * ratio_height / ratio_width)
+    }
+}
+
+/// Fit the image in the center of a black frame.
+fn pad(image: &DynamicImage, (width, height): (u32, u32), filter: FilterType) -> DynamicImage {
+    let image = image.resize(width, height, filter);
+    let mut frame = DynamicImage::new(width, height, image.color());
+    image::imageops::overlay(
+        &mut frame,
+        &image,
+        i64::from((width - image.width()) / 2),
+        i64::from((height - image.height()) / 2),
+    );
+    frame
+}
+
+impl Display for AspectRatio {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (width, height) = self.ratio();
+        write!(f, "{}:{}", width, height)
+    }
+}
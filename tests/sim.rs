@@ -0,0 +1,1484 @@
+use std::{
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use chrono::SubsecRound;
+use ed_journals::logs::content::{
+    log_event_content::screenshot_event::ScreenshotEvent, LogEventContent,
+};
+use ed_timelapse::{
+    screenshot::{CaptureContext, FlightState, ScreenshotTrigger, Watcher},
+    sim::{SimGame, SimTrigger},
+    timelapse::{self, NameTemplate, Session, Settings, SpaceGuard, StoreOptions},
+};
+
+/// Organize the frames in `folder`, with the default names.
+fn store(folder: &Path) -> StoreOptions {
+    StoreOptions {
+        folder: folder.to_owned(),
+        session_template: NameTemplate::default_session(),
+        template: NameTemplate::default(),
+        format: timelapse::OutputFormat::default(),
+        transform: timelapse::FrameTransform::default(),
+        metadata: true,
+        sidecar: false,
+        organize: true,
+        remove_original: true,
+        trash: None,
+        // Whatever the free space of the test machine
+        space: no_space_guard(),
+    }
+}
+
+fn no_space_guard() -> SpaceGuard {
+    SpaceGuard {
+        warn_below: None,
+        pause_below: None,
+        stop_below: None,
+    }
+}
+
+/// A session of screenshots taken manually.
+fn manual_session(folder: &Path) -> Session {
+    let settings = Settings::new(None, Default::default(), false, &store(folder));
+    Session::new(&store(folder), settings).unwrap()
+}
+
+/// The frames stored in `dir`, if it exists, leaving out the session
+/// manifests.
+fn files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = vec![];
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return files;
+    };
+    for entry in entries {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            files.extend(self::files(&path));
+        } else if !path.ends_with(timelapse::MANIFEST_FILE)
+            && !path.ends_with(timelapse::MANIFEST_SNAPSHOT_FILE)
+        {
+            files.push(path);
+        }
+    }
+    files.sort();
+    files
+}
+
+/// How long to wait for a timelapse to reach a state.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Poll until the condition holds, failing the test after [`TIMEOUT`].
+fn wait_for(what: &str, mut condition: impl FnMut() -> bool) {
+    let deadline = Instant::now() + TIMEOUT;
+    while !condition() {
+        assert!(Instant::now() < deadline, "Timed out waiting for {}", what);
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Poll until the timelapse reports a status.
+fn wait_for_status(
+    control: &mut timelapse::TimelapseControl,
+    what: &str,
+    status: impl Fn(&timelapse::Status) -> bool,
+) {
+    wait_for(what, || {
+        control.update_status();
+        status(&control.status)
+    });
+}
+
+#[test]
+fn screenshot_is_found_from_the_journal() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(root.path()).unwrap();
+    let mut watcher = game.watcher().unwrap();
+
+    let screenshot = watcher.take_screenshot(true).unwrap();
+
+    assert_eq!(
+        screenshot.path,
+        game.screenshot_dir().join("Screenshot_0000.bmp")
+    );
+    assert_eq!(screenshot.location, "Earth");
+    assert_eq!(
+        image::image_dimensions(&screenshot.path).unwrap(),
+        (128, 72)
+    );
+}
+
+/// A journal event, as the game writes it.
+fn journal_event(event: serde_json::Value) -> LogEventContent {
+    serde_json::from_value(event).unwrap()
+}
+
+fn touchdown() -> LogEventContent {
+    journal_event(serde_json::json!({
+        "event": "Touchdown",
+        "PlayerControlled": true,
+        "Taxi": false,
+        "Multicrew": false,
+        "StarSystem": "Sol",
+        "SystemAddress": 10477373803u64,
+        "Body": "Earth",
+        "BodyID": 3,
+        "OnStation": false,
+        "OnPlanet": true,
+        "Latitude": 51.5,
+        "Longitude": -0.1
+    }))
+}
+
+fn liftoff() -> LogEventContent {
+    journal_event(serde_json::json!({
+        "event": "Liftoff",
+        "PlayerControlled": true,
+        "Taxi": false,
+        "Multicrew": false,
+        "StarSystem": "Sol",
+        "SystemAddress": 10477373803u64,
+        "Body": "Earth",
+        "BodyID": 3,
+        "OnStation": false,
+        "OnPlanet": true,
+        "Latitude": 51.5,
+        "Longitude": -0.1
+    }))
+}
+
+#[test]
+fn screenshot_carries_the_game_context() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(root.path()).unwrap();
+    let mut watcher = game.watcher().unwrap();
+    for event in [
+        serde_json::json!({
+            "event": "LoadGame",
+            "Commander": "Jameson",
+            "FID": "F1",
+            "Horizons": true,
+            "Ship": "Anaconda",
+            "ShipID": 3,
+            "ShipName": "Dusty",
+            "ShipIdent": "JA-01",
+            "FuelLevel": 32.0,
+            "FuelCapacity": 32.0,
+            "Credits": 1000,
+            "Loan": 0
+        }),
+        serde_json::json!({
+            "event": "SupercruiseExit",
+            "StarSystem": "Sol",
+            "SystemAddress": 10477373803u64,
+            "Body": "Earth",
+            "BodyID": 3,
+            "BodyType": "Planet"
+        }),
+    ] {
+        game.append_event(journal_event(event)).unwrap();
+    }
+    game.append_event(touchdown()).unwrap();
+
+    let screenshot = watcher.take_screenshot(false).unwrap();
+
+    assert_eq!(
+        screenshot.context,
+        CaptureContext {
+            commander: Some("Jameson".to_string()),
+            ship: Some("Anaconda".to_string()),
+            ship_name: Some("Dusty".to_string()),
+            system: Some("Sol".to_string()),
+            system_address: Some(10477373803),
+            star_pos: None,
+            body: Some("Earth".to_string()),
+            station: None,
+            state: FlightState::Landed,
+            music_track: None,
+        }
+    );
+}
+
+#[test]
+fn screenshot_of_another_system_forgets_the_address() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(root.path()).unwrap();
+    let mut watcher = Watcher::new(
+        Box::new(game.trigger().with_location("Achenar", None)),
+        game.journal_dir().to_owned(),
+        game.screenshot_dir().to_owned(),
+    )
+    .unwrap();
+    game.append_event(touchdown()).unwrap();
+
+    let context = watcher.take_screenshot(false).unwrap().context;
+
+    assert_eq!(context.system.as_deref(), Some("Achenar"));
+    assert_eq!(context.system_address, None);
+    assert_eq!(context.star_pos, None);
+}
+
+fn screenshot_event(filename: &str) -> LogEventContent {
+    LogEventContent::Screenshot(ScreenshotEvent {
+        filename: format!("\\ED_Pictures\\{}", filename),
+        width: 64,
+        height: 36,
+        system: None,
+        body: None,
+        latitude: None,
+        longitude: None,
+        altitude: None,
+        heading: None,
+    })
+}
+
+/// Logs unrelated screenshots in the journal just before ours.
+struct InterferingTrigger {
+    game: SimGame,
+    inner: SimTrigger,
+}
+
+impl ScreenshotTrigger for InterferingTrigger {
+    fn trigger(&mut self, high_res: bool) -> anyhow::Result<()> {
+        // A late event from a minute ago
+        let a_minute_ago = chrono::Utc::now() - chrono::Duration::minutes(1);
+        self.game
+            .append_event_at(a_minute_ago, screenshot_event("Screenshot_0000.bmp"))?;
+        // A fresh event about a file that was already there
+        self.game
+            .append_event(screenshot_event("Screenshot_0000.bmp"))?;
+        self.inner.trigger(high_res)
+    }
+}
+
+/// Removes the screenshots just before taking one, like the conversion
+/// workers removing the originals while the next screenshot is requested.
+struct RemovingTrigger {
+    game: SimGame,
+    inner: SimTrigger,
+}
+
+impl ScreenshotTrigger for RemovingTrigger {
+    fn trigger(&mut self, high_res: bool) -> anyhow::Result<()> {
+        for entry in std::fs::read_dir(self.game.screenshot_dir())? {
+            std::fs::remove_file(entry?.path())?;
+        }
+        self.inner.trigger(high_res)
+    }
+}
+
+#[test]
+fn unrelated_screenshot_events_are_ignored() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(root.path()).unwrap();
+    // The player took a screenshot before
+    game.trigger().trigger(false).unwrap();
+    let trigger = InterferingTrigger {
+        game: game.clone(),
+        inner: game.trigger(),
+    };
+    let mut watcher = Watcher::new(
+        Box::new(trigger),
+        game.journal_dir().to_owned(),
+        game.screenshot_dir().to_owned(),
+    )
+    .unwrap();
+
+    let screenshot = watcher.take_screenshot(false).unwrap();
+
+    assert_eq!(
+        screenshot.path,
+        game.screenshot_dir().join("Screenshot_0001.bmp")
+    );
+}
+
+#[test]
+fn screenshots_reusing_a_removed_name_are_found() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(root.path()).unwrap();
+    let trigger = RemovingTrigger {
+        game: game.clone(),
+        inner: game.trigger(),
+    };
+    let mut watcher = Watcher::new(
+        Box::new(trigger),
+        game.journal_dir().to_owned(),
+        game.screenshot_dir().to_owned(),
+    )
+    .unwrap();
+
+    for _ in 0..3 {
+        let screenshot = watcher.take_screenshot(false).unwrap();
+        // The game reuses the name of the removed original
+        assert_eq!(
+            screenshot.path,
+            game.screenshot_dir().join("Screenshot_0000.bmp")
+        );
+    }
+}
+
+#[test]
+fn watcher_follows_the_journal_after_a_restart() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(root.path()).unwrap();
+    let mut watcher = game.watcher().unwrap();
+    watcher.take_screenshot(false).unwrap();
+
+    game.restart().unwrap();
+    let screenshot = watcher.take_screenshot(false).unwrap();
+
+    assert_eq!(files(game.journal_dir()).len(), 2);
+    assert_eq!(
+        screenshot.path,
+        game.screenshot_dir().join("Screenshot_0001.bmp")
+    );
+}
+
+#[test]
+fn watcher_waits_for_the_first_journal() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::installed(root.path()).unwrap();
+    let mut watcher = game.watcher().unwrap();
+
+    game.restart().unwrap();
+    let screenshot = watcher.take_screenshot(false).unwrap();
+
+    assert_eq!(files(game.journal_dir()).len(), 1);
+    assert_eq!(
+        screenshot.path,
+        game.screenshot_dir().join("Screenshot_0000.bmp")
+    );
+}
+
+#[test]
+fn screenshot_is_converted_and_organized() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let mut watcher = game.watcher().unwrap();
+    let folder = root.path().join("timelapses");
+    let session = manual_session(&folder);
+
+    let stored =
+        timelapse::take_screenshot(&mut watcher, &session, "manual", false, &store(&folder))
+            .unwrap();
+
+    assert_eq!(files(&folder), vec![stored.clone()]);
+    assert_eq!(stored.parent().unwrap(), session.folder());
+    assert_eq!(stored.extension().unwrap(), "jpg");
+    assert_eq!(image::image_dimensions(&stored).unwrap(), (64, 36));
+    assert!(files(game.screenshot_dir()).is_empty());
+}
+
+#[test]
+fn frames_are_stored_in_the_chosen_format() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let mut watcher = game.watcher().unwrap();
+    let folder = root.path().join("timelapses");
+    let session = manual_session(&folder);
+
+    for format in [
+        timelapse::OutputFormat::Png,
+        timelapse::OutputFormat::Jpeg { quality: 50 },
+        timelapse::OutputFormat::WebP { quality: None },
+        timelapse::OutputFormat::WebP { quality: Some(50) },
+        timelapse::OutputFormat::Avif { quality: 50 },
+    ] {
+        let store = StoreOptions {
+            format,
+            ..store(&folder)
+        };
+        let stored =
+            timelapse::take_screenshot(&mut watcher, &session, "manual", false, &store).unwrap();
+
+        assert_eq!(stored.extension().unwrap(), format.extension());
+        let bytes = std::fs::read(&stored).unwrap();
+        match format {
+            // Not recognized by `image::guess_format`
+            timelapse::OutputFormat::Avif { .. } => assert_eq!(&bytes[4..12], b"ftypavif"),
+            _ => assert_eq!(
+                image::guess_format(&bytes).unwrap(),
+                image::ImageFormat::from_extension(format.extension()).unwrap()
+            ),
+        }
+        if let timelapse::OutputFormat::WebP { quality } = format {
+            let bitstream = if quality.is_some() { b"VP8 " } else { b"VP8L" };
+            assert!(find(&bytes, bitstream).is_some(), "{}", format);
+        }
+    }
+}
+
+#[test]
+fn frames_are_cropped_and_downscaled() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let mut watcher = game.watcher().unwrap();
+    let folder = root.path().join("timelapses");
+    let session = manual_session(&folder);
+    let store = StoreOptions {
+        transform: timelapse::FrameTransform {
+            aspect: Some(timelapse::AspectRatio::Vertical),
+            max_size: Some(timelapse::Resolution {
+                width: 100,
+                height: 16,
+            }),
+            ..Default::default()
+        },
+        ..store(&folder)
+    };
+
+    let stored =
+        timelapse::take_screenshot(&mut watcher, &session, "manual", false, &store).unwrap();
+
+    // 64x36 cropped to 20x36, then downscaled
+    assert_eq!(image::image_dimensions(&stored).unwrap(), (9, 16));
+}
+
+#[test]
+fn frames_of_another_size_are_padded_or_rejected() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let mut watcher = game.watcher().unwrap();
+    let folder = root.path().join("timelapses");
+    let session = manual_session(&folder);
+    let store = |aspect, mismatch| StoreOptions {
+        format: timelapse::OutputFormat::Png,
+        transform: timelapse::FrameTransform {
+            aspect,
+            mismatch,
+            ..Default::default()
+        },
+        ..store(&folder)
+    };
+    let square = store(
+        Some(timelapse::AspectRatio::Square),
+        timelapse::SizeMismatch::Keep,
+    );
+    let padded = store(None, timelapse::SizeMismatch::Pad);
+    let rejected = store(None, timelapse::SizeMismatch::Reject);
+
+    let first =
+        timelapse::take_screenshot(&mut watcher, &session, "manual", false, &square).unwrap();
+    let padded =
+        timelapse::take_screenshot(&mut watcher, &session, "manual", false, &padded).unwrap();
+    let rejected = timelapse::take_screenshot(&mut watcher, &session, "manual", false, &rejected);
+
+    assert_eq!(image::image_dimensions(&first).unwrap(), (36, 36));
+    let padded = image::open(&padded).unwrap().to_rgb8();
+    assert_eq!(padded.dimensions(), (36, 36));
+    assert_eq!(padded.get_pixel(0, 0), &image::Rgb([0, 0, 0]));
+    assert_ne!(padded.get_pixel(18, 18), &image::Rgb([0, 0, 0]));
+    assert!(rejected.is_err());
+    assert!(matches!(
+        session.manifest().gaps[..],
+        [timelapse::Gap {
+            reason: timelapse::GapReason::Failed { .. },
+            ..
+        }]
+    ));
+}
+
+#[test]
+fn frames_are_sized_like_the_first_captured_one() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let mut watcher = game.watcher().unwrap();
+    let folder = root.path().join("timelapses");
+    let session = manual_session(&folder);
+    let store = StoreOptions {
+        transform: timelapse::FrameTransform {
+            mismatch: timelapse::SizeMismatch::Reject,
+            ..Default::default()
+        },
+        ..store(&folder)
+    };
+
+    let first = timelapse::Job::capture(&mut watcher, &session, "manual", true, &store).unwrap();
+    let second = timelapse::Job::capture(&mut watcher, &session, "manual", false, &store).unwrap();
+    // The second frame is stored first
+    assert!(second.store(&session, &store).is_err());
+    let first = first.store(&session, &store).unwrap();
+
+    assert_eq!(image::image_dimensions(first).unwrap(), (128, 72));
+}
+
+fn find(bytes: &[u8], needle: &[u8]) -> Option<usize> {
+    bytes
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[test]
+fn metadata_is_embedded_in_the_frames() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    game.append_event(touchdown()).unwrap();
+    let mut watcher = game.watcher().unwrap();
+    let folder = root.path().join("timelapses");
+    let session = manual_session(&folder);
+
+    for (format, exif_marker) in [
+        (
+            timelapse::OutputFormat::Jpeg { quality: 90 },
+            b"Exif\0\0".as_slice(),
+        ),
+        (timelapse::OutputFormat::Png, b"eXIf".as_slice()),
+        (
+            timelapse::OutputFormat::WebP { quality: None },
+            b"EXIF".as_slice(),
+        ),
+        (
+            timelapse::OutputFormat::WebP { quality: Some(90) },
+            b"EXIF".as_slice(),
+        ),
+    ] {
+        let store = StoreOptions {
+            format,
+            ..store(&folder)
+        };
+        let stored =
+            timelapse::take_screenshot(&mut watcher, &session, "manual", false, &store).unwrap();
+
+        let bytes = std::fs::read(&stored).unwrap();
+        assert_eq!(
+            image::load_from_memory(&bytes)
+                .unwrap()
+                .into_rgb8()
+                .dimensions(),
+            (64, 36),
+            "{}",
+            format
+        );
+        assert!(find(&bytes, exif_marker).is_some(), "{}", format);
+        let exif = exif::Reader::new()
+            .read_from_container(&mut std::io::Cursor::new(&bytes))
+            .unwrap();
+        let description = exif
+            .get_field(exif::Tag::ImageDescription, exif::In::PRIMARY)
+            .unwrap();
+        assert!(
+            matches!(&description.value, exif::Value::Ascii(values) if values == &[b"Sol, Earth"]),
+            "{}",
+            format
+        );
+        let xmp_start = find(&bytes, b"<x:xmpmeta").unwrap();
+        let xmp_end = find(&bytes, b"</x:xmpmeta>").unwrap() + b"</x:xmpmeta>".len();
+        let xmp = std::str::from_utf8(&bytes[xmp_start..xmp_end]).unwrap();
+        let xmp = roxmltree::Document::parse(xmp).unwrap();
+        let field = |name| {
+            xmp.descendants()
+                .find(|node| node.has_tag_name((timelapse::XMP_NAMESPACE, name)))
+                .and_then(|node| node.text())
+        };
+        assert_eq!(field("System"), Some("Sol"));
+        assert_eq!(field("Body"), Some("Earth"));
+        assert_eq!(field("Session"), Some(session.id()));
+    }
+}
+
+#[test]
+fn sidecars_are_written_next_to_the_frames() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    game.append_event(touchdown()).unwrap();
+    let mut watcher = game.watcher().unwrap();
+    let folder = root.path().join("timelapses");
+    let session = manual_session(&folder);
+    let store = StoreOptions {
+        sidecar: true,
+        ..store(&folder)
+    };
+
+    let stored =
+        timelapse::take_screenshot(&mut watcher, &session, "manual", false, &store).unwrap();
+
+    let path = timelapse::Sidecar::path(&stored);
+    assert_eq!(
+        path.file_name().unwrap().to_str().unwrap(),
+        format!("{}.json", stored.file_name().unwrap().to_str().unwrap())
+    );
+    let sidecar: timelapse::Sidecar =
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    assert_eq!(sidecar.version, timelapse::SIDECAR_VERSION);
+    assert_eq!(sidecar.session, session.id());
+    assert_eq!(sidecar.sequence, 1);
+    assert_eq!(sidecar.event.filename, "\\ED_Pictures\\Screenshot_0000.bmp");
+    assert_eq!((sidecar.event.width, sidecar.event.height), (64, 36));
+    assert_eq!(sidecar.context.body.as_deref(), Some("Earth"));
+    assert_eq!(sidecar.context.state, FlightState::Landed);
+}
+
+#[test]
+fn stale_sidecars_never_fail_a_frame() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let mut watcher = game.watcher().unwrap();
+    let folder = root.path().join("timelapses");
+    let session = manual_session(&folder);
+    let store = StoreOptions {
+        template: NameTemplate::parse("{seq:05}.{ext}").unwrap(),
+        sidecar: true,
+        ..store(&folder)
+    };
+    // Left by a frame that was removed
+    let stale = session.folder().join("00001.jpg.json");
+    std::fs::write(&stale, b"stale").unwrap();
+
+    let job = timelapse::Job::capture(&mut watcher, &session, "manual", false, &store).unwrap();
+    assert_eq!(job.destination, session.folder().join("00002.jpg"));
+    // Appeared once the frame was reserved
+    let appeared = timelapse::Sidecar::path(&job.destination);
+    std::fs::write(&appeared, b"stale").unwrap();
+    let stored = job.store(&session, &store).unwrap();
+
+    assert_eq!(stored, session.folder().join("00002.jpg"));
+    assert!(image::open(&stored).is_ok());
+    assert_eq!(std::fs::read(&stale).unwrap(), b"stale");
+    assert_eq!(std::fs::read(&appeared).unwrap(), b"stale");
+    assert_eq!(session.manifest().frames.len(), 1);
+    assert!(files(game.screenshot_dir()).is_empty());
+}
+
+#[test]
+fn pipeline_stores_the_frames_in_capture_order() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let mut watcher = game.watcher().unwrap();
+    let folder = root.path().join("timelapses");
+    let session = std::sync::Arc::new(manual_session(&folder));
+    let store = store(&folder);
+    let mut pipeline = timelapse::Pipeline::new(session.clone(), store.clone());
+    let backlog = pipeline.backlog();
+
+    // Taken before any conversion, the originals are all kept until then
+    let jobs: Vec<_> = (0..4)
+        .map(|_| timelapse::Job::capture(&mut watcher, &session, "manual", false, &store).unwrap())
+        .collect();
+    for job in jobs {
+        pipeline.submit(job);
+    }
+    pipeline.finish();
+
+    let status = backlog.status();
+    assert_eq!((status.queued, status.converting), (0, 0));
+    assert_eq!((status.stored, status.failed), (4, 0));
+    assert!(status.mean_time.is_some());
+    let manifest = session.manifest();
+    let originals: Vec<_> = manifest
+        .frames
+        .iter()
+        .map(|f| f.original.as_str())
+        .collect();
+    assert_eq!(
+        originals,
+        [
+            "Screenshot_0000.bmp",
+            "Screenshot_0001.bmp",
+            "Screenshot_0002.bmp",
+            "Screenshot_0003.bmp"
+        ]
+    );
+    let stored: Vec<_> = manifest
+        .frames
+        .iter()
+        .map(|frame| session.folder().join(&frame.file))
+        .collect();
+    assert_eq!(stored, files(&folder));
+    assert!(files(game.screenshot_dir()).is_empty());
+}
+
+#[test]
+fn frames_are_numbered_in_their_session() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let mut watcher = game.watcher().unwrap();
+    let folder = root.path().join("timelapses");
+    let session = manual_session(&folder);
+
+    let stored: Vec<_> = (0..3)
+        .map(|_| {
+            timelapse::take_screenshot(&mut watcher, &session, "manual", false, &store(&folder))
+                .unwrap()
+        })
+        .collect();
+    // Another session started in the same second
+    let next_session = manual_session(&folder);
+    let next = timelapse::take_screenshot(
+        &mut watcher,
+        &next_session,
+        "manual",
+        false,
+        &store(&folder),
+    )
+    .unwrap();
+
+    assert_eq!(files(session.folder()), stored);
+    for (i, path) in stored.iter().enumerate() {
+        let name = path.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with(&format!("{:05}_", i + 1)), "{}", name);
+    }
+    assert_ne!(next_session.folder(), session.folder());
+    assert_eq!(files(next_session.folder()), vec![next]);
+}
+
+#[test]
+fn frames_are_named_from_the_template() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let trigger = game
+        .trigger()
+        .with_location("Col 285 Sector AB-C d1/2", Some("Cmdr's <Base>"));
+    let mut watcher = Watcher::new(
+        Box::new(trigger),
+        game.journal_dir().to_owned(),
+        game.screenshot_dir().to_owned(),
+    )
+    .unwrap();
+    let folder = root.path().join("timelapses");
+    let store = StoreOptions {
+        template: NameTemplate::parse("{system}/{body}/{trigger}_{seq:03}.{ext}").unwrap(),
+        ..store(&folder)
+    };
+
+    let session = manual_session(&folder);
+
+    let stored =
+        timelapse::take_screenshot(&mut watcher, &session, "manual", false, &store).unwrap();
+
+    assert_eq!(
+        stored,
+        session
+            .folder()
+            .join("Col 285 Sector AB-C d1_2")
+            .join("Cmdr's _Base_")
+            .join("manual_001.jpg")
+    );
+}
+
+#[test]
+fn invalid_templates_are_rejected() {
+    for template in [
+        "",
+        "{nope}",
+        "{seq",
+        "/abs/{seq}",
+        "../{seq}",
+        "{body:03}",
+        "{date}/",
+        "{session}/{seq}.{ext}",
+    ] {
+        assert!(NameTemplate::parse(template).is_err(), "{}", template);
+    }
+    for template in ["", "{seq}", "{system}", "{date}/{time}", "{nope}"] {
+        assert!(
+            NameTemplate::parse_session(template).is_err(),
+            "{}",
+            template
+        );
+    }
+}
+
+#[test]
+fn session_folders_are_named_from_their_template() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let mut watcher = game.watcher().unwrap();
+    let folder = root.path().join("timelapses");
+    let store = StoreOptions {
+        session_template: NameTemplate::parse_session("Trip {year}").unwrap(),
+        ..store(&folder)
+    };
+    let settings = Settings::new(None, Default::default(), false, &store);
+    let first = Session::new(&store, settings.clone()).unwrap();
+    timelapse::take_screenshot(&mut watcher, &first, "manual", false, &store).unwrap();
+    let second = Session::new(&store, settings).unwrap();
+
+    let year = first.started().format("%Y");
+    assert_eq!(first.folder(), folder.join(format!("Trip {}", year)));
+    assert_eq!(second.folder(), folder.join(format!("Trip {} (2)", year)));
+    assert_eq!(first.manifest().settings.session_template, "Trip {year}");
+}
+
+#[test]
+fn sessions_started_together_have_their_own_folder() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let mut watcher = game.watcher().unwrap();
+    let folder = root.path().join("timelapses");
+    let store = StoreOptions {
+        session_template: NameTemplate::parse_session("{date}").unwrap(),
+        ..store(&folder)
+    };
+    let settings = Settings::new(None, Default::default(), false, &store);
+
+    // Both started before any capture
+    let first = Session::new(&store, settings.clone()).unwrap();
+    let second = Session::new(&store, settings.clone()).unwrap();
+    timelapse::take_screenshot(&mut watcher, &second, "manual", false, &store).unwrap();
+    timelapse::take_screenshot(&mut watcher, &first, "manual", false, &store).unwrap();
+    first.stop();
+    second.stop();
+
+    assert_ne!(first.folder(), second.folder());
+    for session in [&first, &second] {
+        let manifest_file = session.folder().join(timelapse::MANIFEST_FILE);
+        let manifest = timelapse::Manifest::read(&manifest_file).unwrap();
+        assert_eq!(manifest.session, session.id());
+        assert_eq!(manifest.frames.len(), 1);
+    }
+
+    // A session without any capture leaves nothing behind
+    let empty = Session::new(&store, settings).unwrap();
+    assert!(empty.folder().is_dir());
+    empty.stop();
+    assert!(!empty.folder().exists());
+}
+
+#[test]
+fn removed_originals_are_moved_to_the_trash() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let mut watcher = game.watcher().unwrap();
+    let folder = root.path().join("timelapses");
+    let trash = root.path().join("trash");
+    let session = manual_session(&folder);
+    let store = StoreOptions {
+        trash: Some(trash.clone()),
+        ..store(&folder)
+    };
+
+    for _ in 0..2 {
+        timelapse::take_screenshot(&mut watcher, &session, "manual", false, &store).unwrap();
+    }
+
+    assert!(files(game.screenshot_dir()).is_empty());
+    // The game reuses the name of the removed screenshot
+    let trash = trash.join(session.id());
+    assert_eq!(
+        files(&trash),
+        vec![
+            trash.join("Screenshot_0000 (2).bmp"),
+            trash.join("Screenshot_0000.bmp")
+        ]
+    );
+    // Nothing is left from the atomic writes: the frames, the manifest and
+    // its snapshot
+    assert_eq!(std::fs::read_dir(session.folder()).unwrap().count(), 4);
+    assert_eq!(files(session.folder()).len(), 2);
+}
+
+#[test]
+fn frames_never_replace_a_file_appeared_meanwhile() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let mut watcher = game.watcher().unwrap();
+    let folder = root.path().join("timelapses");
+    let session = manual_session(&folder);
+    let store = store(&folder);
+
+    let job = timelapse::Job::capture(&mut watcher, &session, "manual", false, &store).unwrap();
+    // Another instance of the app stores a frame there
+    let reserved = job.destination.clone();
+    std::fs::create_dir_all(reserved.parent().unwrap()).unwrap();
+    std::fs::write(&reserved, b"not ours").unwrap();
+    let stored = job.store(&session, &store).unwrap();
+
+    assert_ne!(stored, reserved);
+    assert_eq!(std::fs::read(&reserved).unwrap(), b"not ours");
+    assert!(image::open(&stored).is_ok());
+    assert_eq!(
+        session.manifest().frames[0].file,
+        stored.strip_prefix(session.folder()).unwrap()
+    );
+}
+
+#[test]
+fn original_is_kept_when_not_organizing() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let mut watcher = game.watcher().unwrap();
+    let folder = root.path().join("timelapses");
+
+    let path = timelapse::take_screenshot(
+        &mut watcher,
+        &manual_session(&folder),
+        "manual",
+        false,
+        &StoreOptions {
+            organize: false,
+            ..store(&folder)
+        },
+    )
+    .unwrap();
+
+    assert_eq!(files(game.screenshot_dir()), vec![path]);
+    assert!(files(&folder).is_empty());
+}
+
+#[test]
+fn frames_are_not_stored_on_a_full_disk() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let mut watcher = game.watcher().unwrap();
+    let folder = root.path().join("timelapses");
+    let session = manual_session(&folder);
+    let store = StoreOptions {
+        space: SpaceGuard {
+            pause_below: Some(u64::MAX),
+            ..no_space_guard()
+        },
+        ..store(&folder)
+    };
+
+    let error = timelapse::take_screenshot(&mut watcher, &session, "manual", false, &store)
+        .unwrap_err()
+        .to_string();
+
+    assert!(error.contains("Not enough space"), "{}", error);
+    assert_eq!(files(game.screenshot_dir()).len(), 1);
+    assert!(files(&folder).is_empty());
+    assert!(matches!(
+        session.manifest().gaps[..],
+        [timelapse::Gap {
+            reason: timelapse::GapReason::Failed { .. },
+            ..
+        }]
+    ));
+}
+
+#[test]
+fn free_space_is_read_from_the_closest_folder() {
+    let root = tempfile::tempdir().unwrap();
+
+    let free = timelapse::free_space(&root.path().join("not/created/yet")).unwrap();
+
+    assert!(free > 0);
+    assert_eq!(
+        timelapse::SpaceGuard {
+            stop_below: Some(u64::MAX),
+            ..no_space_guard()
+        }
+        .check([root.path()])
+        .map(|low| low.action),
+        Some(timelapse::SpaceAction::Stop)
+    );
+}
+
+#[test]
+fn space_needed_depends_on_the_stored_frames() {
+    let folder = Path::new("timelapses");
+    let screenshot = timelapse::Resolution::default();
+    let estimate = |store: StoreOptions| timelapse::frame_size_estimate(&store, screenshot);
+    let jpeg = estimate(store(folder));
+    let png = estimate(StoreOptions {
+        format: timelapse::OutputFormat::Png,
+        ..store(folder)
+    });
+    let downscaled = estimate(StoreOptions {
+        transform: timelapse::FrameTransform {
+            max_size: Some(timelapse::Resolution {
+                width: 960,
+                height: 540,
+            }),
+            ..Default::default()
+        },
+        ..store(folder)
+    });
+    let with_originals = estimate(StoreOptions {
+        remove_original: false,
+        ..store(folder)
+    });
+    let with_trash = estimate(StoreOptions {
+        trash: Some(folder.join("trash")),
+        ..store(folder)
+    });
+
+    assert!(0 < jpeg && jpeg < png);
+    assert_eq!(downscaled * 4 / jpeg, 1);
+    assert_eq!(with_originals, jpeg + 1920 * 1080 * 3);
+    assert_eq!(with_trash, with_originals);
+}
+
+#[test]
+fn timelapse_pauses_when_the_disk_is_almost_full() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let folder = root.path().join("timelapses");
+
+    let mut control = timelapse::TimelapseControl::start(
+        game.watcher().unwrap(),
+        timelapse::Schedule::Interval(Duration::from_millis(500)),
+        timelapse::CaptureRules::default(),
+        false,
+        StoreOptions {
+            space: SpaceGuard {
+                pause_below: Some(u64::MAX),
+                ..no_space_guard()
+            },
+            ..store(&folder)
+        },
+    )
+    .unwrap();
+    wait_for_status(&mut control, "the pause", |status| {
+        matches!(status, timelapse::Status::OutOfSpace(_))
+    });
+
+    assert!(control.is_paused());
+    control.stop();
+    assert!(files(&folder).is_empty());
+    assert!(files(game.screenshot_dir()).is_empty());
+}
+
+#[test]
+fn timelapse_stops_when_the_disk_is_full() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let folder = root.path().join("timelapses");
+
+    let mut control = timelapse::TimelapseControl::start(
+        game.watcher().unwrap(),
+        timelapse::Schedule::Interval(Duration::from_millis(500)),
+        timelapse::CaptureRules::default(),
+        false,
+        StoreOptions {
+            space: SpaceGuard {
+                warn_below: Some(u64::MAX),
+                stop_below: Some(u64::MAX),
+                ..no_space_guard()
+            },
+            ..store(&folder)
+        },
+    )
+    .unwrap();
+    wait_for_status(&mut control, "the stop", |status| {
+        matches!(status, timelapse::Status::Stopped(_))
+    });
+
+    assert!(control.is_stopped());
+    control.stop();
+    assert!(files(&folder).is_empty());
+    assert!(files(game.screenshot_dir()).is_empty());
+}
+
+#[test]
+fn timelapse_captures_at_interval() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let folder = root.path().join("timelapses");
+
+    let mut control = timelapse::TimelapseControl::start(
+        game.watcher().unwrap(),
+        timelapse::Schedule::Interval(Duration::from_millis(300)),
+        timelapse::CaptureRules::default(),
+        false,
+        store(&folder),
+    )
+    .unwrap();
+    wait_for("two frames", || files(&folder).len() >= 2);
+    control.stop();
+}
+
+#[test]
+fn timelapse_finishes_in_the_background_once_asked_to_stop() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let folder = root.path().join("timelapses");
+
+    let mut control = timelapse::TimelapseControl::start(
+        game.watcher().unwrap(),
+        timelapse::Schedule::Interval(Duration::from_secs(1)),
+        timelapse::CaptureRules::default(),
+        false,
+        store(&folder),
+    )
+    .unwrap();
+    wait_for("two screenshots", || {
+        let conversion = control.conversion();
+        conversion.stored + (conversion.queued + conversion.converting) as u64 >= 2
+    });
+    control.request_stop();
+    wait_for("the end of the timelapse", || control.is_finished());
+
+    assert!(files(&folder).len() >= 2);
+    let manifest_file = control.session_folder().join(timelapse::MANIFEST_FILE);
+    let manifest = timelapse::Manifest::read(&manifest_file).unwrap();
+    assert!(manifest.stopped.is_some());
+}
+
+#[test]
+fn session_manifest_records_the_frames() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let folder = root.path().join("timelapses");
+    let schedule = timelapse::Schedule::Interval(Duration::from_millis(300));
+
+    let mut control = timelapse::TimelapseControl::start(
+        game.watcher().unwrap(),
+        schedule.clone(),
+        timelapse::CaptureRules::default(),
+        false,
+        store(&folder),
+    )
+    .unwrap();
+    wait_for("two frames", || files(&folder).len() >= 2);
+    control.stop();
+
+    let session_folder = control.session_folder();
+    let manifest_file = session_folder.join(timelapse::MANIFEST_FILE);
+    let manifest = timelapse::Manifest::read(&manifest_file).unwrap();
+    assert!(manifest.stopped.is_some());
+    assert_eq!(manifest.settings.schedule, Some(schedule));
+    assert_eq!(manifest.settings.format, timelapse::OutputFormat::default());
+    let stored: Vec<_> = manifest
+        .frames
+        .iter()
+        .map(|frame| session_folder.join(&frame.file))
+        .collect();
+    assert_eq!(stored, files(&folder));
+    for (i, frame) in manifest.frames.iter().enumerate() {
+        assert_eq!(frame.sequence, i as u32 + 1);
+        // The originals are removed, the game reuses the name
+        assert_eq!(frame.original, "Screenshot_0000.bmp");
+        assert_eq!(frame.trigger, "interval");
+        assert_eq!(frame.location, "Earth");
+        assert!(frame.logged >= frame.requested.with_timezone(&chrono::Utc).trunc_subsecs(0));
+    }
+
+    // One line per record, the start, the frames, the gaps and the stop
+    let content = std::fs::read_to_string(&manifest_file).unwrap();
+    assert_eq!(
+        content.lines().count(),
+        manifest.frames.len() + manifest.gaps.len() + 2
+    );
+    // The snapshot, for the other tools
+    let snapshot = std::fs::read(session_folder.join(timelapse::MANIFEST_SNAPSHOT_FILE)).unwrap();
+    let snapshot: timelapse::Manifest = serde_json::from_slice(&snapshot).unwrap();
+    assert_eq!(snapshot, manifest);
+    // A line cut by a crash is ignored
+    std::fs::write(&manifest_file, format!("{}{{\"frame\":{{", content)).unwrap();
+    assert_eq!(timelapse::Manifest::read(&manifest_file).unwrap(), manifest);
+}
+
+#[test]
+fn session_manifest_is_written_when_not_organizing() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let folder = root.path().join("timelapses");
+
+    let mut control = timelapse::TimelapseControl::start(
+        game.watcher().unwrap(),
+        timelapse::Schedule::Interval(Duration::from_millis(300)),
+        timelapse::CaptureRules::default(),
+        false,
+        StoreOptions {
+            organize: false,
+            ..store(&folder)
+        },
+    )
+    .unwrap();
+    wait_for("two screenshots", || {
+        files(game.screenshot_dir()).len() >= 2
+    });
+    control.stop();
+
+    // Only the manifest is in the session folder
+    assert!(files(&folder).is_empty());
+    let manifest_file = control.session_folder().join(timelapse::MANIFEST_FILE);
+    let manifest = timelapse::Manifest::read(&manifest_file).unwrap();
+    assert!(manifest.stopped.is_some());
+    let originals: Vec<_> = manifest
+        .frames
+        .iter()
+        .map(|frame| frame.file.clone())
+        .collect();
+    assert_eq!(originals, files(game.screenshot_dir()));
+}
+
+#[test]
+fn timelapse_captures_on_events() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let folder = root.path().join("timelapses");
+    // Logged before the timelapse started
+    let an_hour_ago = chrono::Utc::now() - chrono::Duration::hours(1);
+    game.append_event_at(an_hour_ago, touchdown()).unwrap();
+    let just_before = chrono::Utc::now();
+
+    let mut control = timelapse::TimelapseControl::start(
+        game.watcher().unwrap(),
+        timelapse::Schedule::Events(timelapse::EventTriggers {
+            fsd_jump: false,
+            landing: true,
+            delay: Duration::from_millis(500),
+            ..Default::default()
+        }),
+        timelapse::CaptureRules::default(),
+        false,
+        store(&folder),
+    )
+    .unwrap();
+    // Logged after the timelapse started, but in the second before
+    game.append_event_at(just_before, touchdown()).unwrap();
+    // Logged in the next second, so after the start. It would make a single
+    // screenshot with the touchdowns, named after them, were they triggering
+    let just_after = chrono::Utc::now() + chrono::Duration::seconds(1);
+    game.append_event_at(just_after, liftoff()).unwrap();
+    wait_for("the frame of the liftoff", || !files(&folder).is_empty());
+    control.stop();
+
+    let manifest_file = control.session_folder().join(timelapse::MANIFEST_FILE);
+    let manifest = timelapse::Manifest::read(&manifest_file).unwrap();
+    let triggers: Vec<_> = manifest.frames.iter().map(|f| f.trigger.as_str()).collect();
+    assert_eq!(triggers, ["Liftoff"]);
+}
+
+#[test]
+fn timelapse_can_be_paused() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let folder = root.path().join("timelapses");
+
+    let mut control = timelapse::TimelapseControl::start(
+        game.watcher().unwrap(),
+        timelapse::Schedule::Interval(Duration::from_millis(300)),
+        timelapse::CaptureRules::default(),
+        false,
+        store(&folder),
+    )
+    .unwrap();
+    wait_for("a frame", || !files(&folder).is_empty());
+    control.pause();
+    // After the capture in progress, if any, is stored
+    wait_for_status(&mut control, "the pause", |status| {
+        matches!(status, timelapse::Status::Paused)
+    });
+    wait_for("the conversions", || {
+        let conversion = control.conversion();
+        conversion.queued + conversion.converting == 0
+    });
+    let before_pause = files(&folder).len();
+
+    // A few intervals
+    thread::sleep(Duration::from_millis(1000));
+    assert_eq!(files(&folder).len(), before_pause);
+
+    control.resume();
+    wait_for("a frame after the pause", || {
+        files(&folder).len() > before_pause
+    });
+    control.stop();
+}
+
+#[test]
+fn timelapse_is_suspended_in_the_galaxy_map() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let folder = root.path().join("timelapses");
+    let status_file = game.journal_dir().join("Status.json");
+    std::fs::write(
+        &status_file,
+        r#"{ "timestamp":"2024-06-22T20:00:00Z", "event":"Status", "Flags":16777240, "GuiFocus":6 }"#,
+    )
+    .unwrap();
+
+    let mut control = timelapse::TimelapseControl::start(
+        game.watcher().unwrap(),
+        timelapse::Schedule::Interval(Duration::from_secs(1)),
+        timelapse::CaptureRules::default(),
+        false,
+        store(&folder),
+    )
+    .unwrap();
+    wait_for_status(&mut control, "the suspension", |status| {
+        matches!(
+            status,
+            timelapse::Status::Suspended(timelapse::Suspension::GalaxyMap)
+        )
+    });
+    assert!(files(&folder).is_empty());
+
+    std::fs::write(
+        &status_file,
+        r#"{ "timestamp":"2024-06-22T20:00:05Z", "event":"Status", "Flags":16777240, "GuiFocus":0 }"#,
+    )
+    .unwrap();
+    wait_for("a frame", || !files(&folder).is_empty());
+    control.stop();
+}
+
+#[test]
+fn timelapse_is_suspended_while_the_game_is_minimized() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let folder = root.path().join("timelapses");
+    game.set_minimized(true);
+
+    let mut control = timelapse::TimelapseControl::start(
+        game.watcher().unwrap(),
+        timelapse::Schedule::Interval(Duration::from_millis(500)),
+        timelapse::CaptureRules::default(),
+        false,
+        store(&folder),
+    )
+    .unwrap();
+    wait_for_status(&mut control, "the suspension", |status| {
+        matches!(
+            status,
+            timelapse::Status::Suspended(timelapse::Suspension::Minimized)
+        )
+    });
+    assert!(files(&folder).is_empty());
+
+    game.set_minimized(false);
+    wait_for("a frame", || !files(&folder).is_empty());
+    control.stop();
+}
+
+#[test]
+fn timelapse_is_suspended_in_the_main_menu() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let folder = root.path().join("timelapses");
+    game.append_event(journal_event(serde_json::json!({
+        "event": "Music",
+        "MusicTrack": "MainMenu"
+    })))
+    .unwrap();
+
+    let mut control = timelapse::TimelapseControl::start(
+        game.watcher().unwrap(),
+        timelapse::Schedule::Interval(Duration::from_secs(1)),
+        timelapse::CaptureRules::default(),
+        false,
+        store(&folder),
+    )
+    .unwrap();
+    wait_for_status(&mut control, "the suspension", |status| {
+        matches!(
+            status,
+            timelapse::Status::Suspended(timelapse::Suspension::MainMenu)
+        )
+    });
+    assert!(files(&folder).is_empty());
+
+    game.append_event(journal_event(serde_json::json!({
+        "event": "Music",
+        "MusicTrack": "Exploration"
+    })))
+    .unwrap();
+    wait_for("a frame", || !files(&folder).is_empty());
+    control.stop();
+}
+
+/// Why the captures of a stopped timelapse were skipped.
+fn skipped(control: &timelapse::TimelapseControl) -> Vec<String> {
+    let manifest_file = control.session_folder().join(timelapse::MANIFEST_FILE);
+    let manifest = timelapse::Manifest::read(&manifest_file).unwrap();
+    manifest
+        .gaps
+        .into_iter()
+        .filter_map(|gap| match gap.reason {
+            timelapse::GapReason::Skipped { why } => Some(why),
+            _ => None,
+        })
+        .collect()
+}
+
+fn start_jump() -> LogEventContent {
+    journal_event(serde_json::json!({
+        "event": "StartJump",
+        "JumpType": "Hyperspace",
+        "StarSystem": "Alpha Centauri",
+        "SystemAddress": 1458376315610u64,
+        "StarClass": "G"
+    }))
+}
+
+fn fsd_jump() -> LogEventContent {
+    journal_event(serde_json::json!({
+        "event": "FSDJump",
+        "StarSystem": "Alpha Centauri",
+        "SystemAddress": 1458376315610u64,
+        "StarPos": [3.03125, -0.09375, 3.15625],
+        "SystemEconomy": "$economy_None;",
+        "SystemSecondEconomy": "$economy_None;",
+        "SystemGovernment": "$government_None;",
+        "SystemSecurity": "$GAlAXY_MAP_INFO_state_anarchy;",
+        "Population": 0,
+        "Body": "Alpha Centauri A",
+        "BodyID": 1,
+        "BodyType": "Star",
+        "JumpDist": 4.377,
+        "FuelUsed": 0.5,
+        "FuelLevel": 31.5
+    }))
+}
+
+#[test]
+fn hyperspace_frames_are_skipped() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let folder = root.path().join("timelapses");
+    game.append_event(start_jump()).unwrap();
+
+    let mut control = timelapse::TimelapseControl::start(
+        game.watcher().unwrap(),
+        timelapse::Schedule::Interval(Duration::from_secs(1)),
+        timelapse::CaptureRules::default(),
+        false,
+        store(&folder),
+    )
+    .unwrap();
+    // Waiting for the next capture after the skipped one
+    wait_for_status(&mut control, "a skipped capture", |status| {
+        matches!(status, timelapse::Status::Waiting(_))
+    });
+    assert!(files(&folder).is_empty());
+
+    game.append_event(fsd_jump()).unwrap();
+    wait_for("a frame", || !files(&folder).is_empty());
+    control.stop();
+
+    assert_eq!(skipped(&control), ["in hyperspace"]);
+}
+
+#[test]
+fn loading_screen_frames_are_skipped() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let folder = root.path().join("timelapses");
+    game.append_event(start_jump()).unwrap();
+    // Still jumping after the tunnel, while the next system loads
+    let status_file = game.journal_dir().join("Status.json");
+    std::fs::write(
+        &status_file,
+        r#"{ "timestamp":"2024-06-22T20:00:00Z", "event":"Status", "Flags":1090519064, "GuiFocus":0 }"#,
+    )
+    .unwrap();
+
+    let mut control = timelapse::TimelapseControl::start(
+        game.watcher().unwrap(),
+        timelapse::Schedule::Interval(Duration::from_secs(1)),
+        timelapse::CaptureRules::default(),
+        false,
+        store(&folder),
+    )
+    .unwrap();
+    wait_for_status(&mut control, "a skipped capture", |status| {
+        matches!(status, timelapse::Status::Waiting(_))
+    });
+    assert!(files(&folder).is_empty());
+
+    std::fs::write(
+        &status_file,
+        r#"{ "timestamp":"2024-06-22T20:00:05Z", "event":"Status", "Flags":16777240, "GuiFocus":0 }"#,
+    )
+    .unwrap();
+    game.append_event(fsd_jump()).unwrap();
+    wait_for("a frame", || !files(&folder).is_empty());
+    control.stop();
+
+    assert_eq!(skipped(&control), ["in hyperspace"]);
+}
@@ -0,0 +1,73 @@
+//! Checks that the background threads are stopped. This is in its own test
+//! binary so that no other test spawns threads while counting them.
+#![cfg(target_os = "linux")]
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use ed_timelapse::{
+    sim::SimGame,
+    timelapse::{
+        CaptureRules, FrameTransform, NameTemplate, OutputFormat, Schedule, SpaceGuard,
+        StoreOptions, TimelapseControl,
+    },
+};
+
+fn thread_count() -> usize {
+    std::fs::read_dir("/proc/self/task").unwrap().count()
+}
+
+/// Some threads, like the file system watchers, are only asked to stop.
+fn settled_thread_count(expected: usize) -> usize {
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while thread_count() != expected && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    thread_count()
+}
+
+#[test]
+fn threads_are_stopped() {
+    let root = tempfile::tempdir().unwrap();
+    let game = SimGame::new(&root.path().join("game")).unwrap();
+    let folder = root.path().join("timelapses");
+    let before = thread_count();
+
+    for _ in 0..5 {
+        drop(game.watcher().unwrap());
+
+        let mut control = TimelapseControl::start(
+            game.watcher().unwrap(),
+            Schedule::Interval(Duration::from_secs(3600)),
+            CaptureRules::default(),
+            false,
+            StoreOptions {
+                folder: folder.clone(),
+                session_template: NameTemplate::default_session(),
+                template: NameTemplate::default(),
+                format: OutputFormat::default(),
+                transform: FrameTransform::default(),
+                metadata: false,
+                sidecar: false,
+                organize: false,
+                remove_original: false,
+                trash: None,
+                space: SpaceGuard {
+                    warn_below: None,
+                    pause_below: None,
+                    stop_below: None,
+                },
+            },
+        )
+        .unwrap();
+        // Let the first capture happen, the timelapse then waits for an hour
+        thread::sleep(Duration::from_millis(500));
+        let stopping = Instant::now();
+        control.stop();
+        assert!(stopping.elapsed() < Duration::from_secs(1));
+    }
+
+    assert_eq!(settled_thread_count(before), before);
+}